@@ -0,0 +1,41 @@
+// Render the scene of the book from several angles, one image per frame, all
+// in the same process and with the same renderer.
+//
+// cargo run --release --example orbit
+
+extern crate nalgebra_glm as glm;
+
+use ray_tracing_in_one_we::{scene, Camera, OutputFormat, RenderSettings, Renderer};
+
+const FRAMES: usize = 8;
+
+fn main() {
+    let settings = RenderSettings {
+        width: 300,
+        height: 200,
+        samples_per_pixel: 20,
+        ..scene::render_settings()
+    };
+    let mut scene = scene::build_scene(&settings);
+    let renderer = Renderer::new(settings.clone());
+
+    for frame in 0..FRAMES {
+        let angle = frame as f32 / FRAMES as f32 * std::f32::consts::TAU;
+        let look_from = glm::vec3(13.0 * angle.cos(), 2.0, 13.0 * angle.sin());
+        scene.camera = Camera::new(
+            look_from,
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            20.0,
+            settings.aspect_ratio(),
+            0.1,
+            10.0,
+        );
+        let path = format!("orbit_{frame:02}.png");
+        renderer
+            .render(&scene)
+            .save(&path, OutputFormat::Png)
+            .unwrap();
+        eprintln!("Saved {path}");
+    }
+}
@@ -0,0 +1,50 @@
+use crate::ray::Ray;
+
+// An axis-aligned bounding box, used by the BVH to quickly discard rays
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Aabb {
+    pub const fn new(min: glm::Vec3, max: glm::Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // The smallest box containing both boxes
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            glm::min2(&self.min, &other.min),
+            glm::max2(&self.max, &other.max),
+        )
+    }
+
+    pub fn centroid(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    // Slab test. Rays parallel to an axis give infinite t values which the
+    // min/max handle correctly
+    pub fn hit(&self, ray: &Ray, mut t_min: f32, mut t_max: f32) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.dir[axis];
+            let mut t0 = (self.min[axis] - ray.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - ray.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
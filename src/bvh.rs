@@ -0,0 +1,198 @@
+use crate::aabb::Aabb;
+use crate::hittable::{HitRecord, Hittable};
+use crate::hittable_list::{HittableList, HittableObject};
+use crate::ray::Ray;
+
+// Leaves never get split below this amount of objects
+const MAX_LEAF_SIZE: usize = 4;
+// Number of buckets used to evaluate the surface area heuristic
+const SAH_BUCKETS: usize = 12;
+// Cost of traversing a node relative to the cost of intersecting one object
+const TRAVERSAL_COST: f32 = 0.125;
+
+// A bounding volume hierarchy built with the surface area heuristic. Objects
+// without a bounding box (if any) are kept aside and tested linearly
+pub struct Bvh {
+    root: Option<BvhNode>,
+    unbounded: HittableList,
+}
+
+pub enum BvhNode {
+    Leaf {
+        bbox: Aabb,
+        objects: Vec<HittableObject>,
+    },
+    Branch {
+        bbox: Aabb,
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+impl Bvh {
+    pub fn new(list: HittableList) -> Self {
+        let mut unbounded = HittableList::default();
+        let mut bounded = Vec::with_capacity(list.objects.len());
+        for object in list.objects {
+            match object.bounding_box() {
+                Some(bbox) => bounded.push((bbox, object)),
+                None => unbounded.add(object),
+            }
+        }
+        let root = if bounded.is_empty() {
+            None
+        } else {
+            Some(BvhNode::build(bounded))
+        };
+        Self { root, unbounded }
+    }
+}
+
+impl From<HittableList> for Bvh {
+    fn from(list: HittableList) -> Self {
+        Bvh::new(list)
+    }
+}
+
+impl Hittable for Bvh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let bounded = self
+            .root
+            .as_ref()
+            .and_then(|root| root.hit(ray, t_min, t_max));
+        let t_max = bounded.as_ref().map(|r| r.t).unwrap_or(t_max);
+        self.unbounded.hit(ray, t_min, t_max).or(bounded)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if !self.unbounded.objects.is_empty() {
+            return None;
+        }
+        self.root.as_ref().map(BvhNode::bbox)
+    }
+}
+
+impl BvhNode {
+    fn build(mut objects: Vec<(Aabb, HittableObject)>) -> Self {
+        let bbox = objects
+            .iter()
+            .map(|(b, _)| *b)
+            .reduce(|a, b| a.surrounding(&b))
+            .expect("BvhNode can't be built without objects");
+        if objects.len() <= MAX_LEAF_SIZE {
+            return BvhNode::leaf(bbox, objects);
+        }
+
+        // Split along the axis where the centroids are the most spread
+        let centroid_bounds = objects
+            .iter()
+            .map(|(b, _)| Aabb::new(b.centroid(), b.centroid()))
+            .reduce(|a, b| a.surrounding(&b))
+            .unwrap();
+        let extent = centroid_bounds.max - centroid_bounds.min;
+        let axis = extent.iamax();
+        if extent[axis] <= 0.0 {
+            // Every centroid is at the same place, the SAH can't help here
+            let right = objects.split_off(objects.len() / 2);
+            return BvhNode::branch(bbox, objects, right);
+        }
+
+        let bucket_of = |b: &Aabb| {
+            let offset = (b.centroid()[axis] - centroid_bounds.min[axis]) / extent[axis];
+            ((offset * SAH_BUCKETS as f32) as usize).min(SAH_BUCKETS - 1)
+        };
+        let mut buckets: [(usize, Option<Aabb>); SAH_BUCKETS] = [(0, None); SAH_BUCKETS];
+        for (b, _) in &objects {
+            let bucket = &mut buckets[bucket_of(b)];
+            bucket.0 += 1;
+            bucket.1 = Some(bucket.1.map_or(*b, |bb| bb.surrounding(b)));
+        }
+
+        // Cost of splitting after each bucket
+        let parent_area = bbox.surface_area();
+        let (split, cost) = (0..SAH_BUCKETS - 1)
+            .map(|split| {
+                let side = |range: &[(usize, Option<Aabb>)]| {
+                    range
+                        .iter()
+                        .fold((0, None::<Aabb>), |(n, acc), (count, b)| {
+                            let acc = match (acc, b) {
+                                (Some(a), Some(b)) => Some(a.surrounding(b)),
+                                (a, b) => a.or(*b),
+                            };
+                            (n + count, acc)
+                        })
+                };
+                let (n_left, b_left) = side(&buckets[..=split]);
+                let (n_right, b_right) = side(&buckets[split + 1..]);
+                let area = |b: Option<Aabb>| b.map_or(0.0, |b| b.surface_area());
+                let cost = TRAVERSAL_COST
+                    + (n_left as f32 * area(b_left) + n_right as f32 * area(b_right)) / parent_area;
+                (split, cost)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+
+        if cost >= objects.len() as f32 && objects.len() <= MAX_LEAF_SIZE * 4 {
+            return BvhNode::leaf(bbox, objects);
+        }
+        let (left, right): (Vec<_>, Vec<_>) = objects
+            .into_iter()
+            .partition(|(b, _)| bucket_of(b) <= split);
+        BvhNode::branch(bbox, left, right)
+    }
+
+    fn leaf(bbox: Aabb, objects: Vec<(Aabb, HittableObject)>) -> Self {
+        BvhNode::Leaf {
+            bbox,
+            objects: objects.into_iter().map(|(_, o)| o).collect(),
+        }
+    }
+
+    fn branch(
+        bbox: Aabb,
+        left: Vec<(Aabb, HittableObject)>,
+        right: Vec<(Aabb, HittableObject)>,
+    ) -> Self {
+        BvhNode::Branch {
+            bbox,
+            left: Box::new(BvhNode::build(left)),
+            right: Box::new(BvhNode::build(right)),
+        }
+    }
+
+    pub fn bbox(&self) -> Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } | BvhNode::Branch { bbox, .. } => *bbox,
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        if !self.bbox().hit(ray, t_min, t_max) {
+            return None;
+        }
+        match self {
+            BvhNode::Leaf { objects, .. } => {
+                let mut output_rec: Option<HitRecord> = None;
+                for object in objects {
+                    let t_max = output_rec.as_ref().map(|r| r.t).unwrap_or(t_max);
+                    if let Some(rec) = object.hit(ray, t_min, t_max) {
+                        output_rec = Some(rec);
+                    }
+                }
+                output_rec
+            }
+            BvhNode::Branch { left, right, .. } => {
+                let left_rec = left.hit(ray, t_min, t_max);
+                let t_max = left_rec.as_ref().map(|r| r.t).unwrap_or(t_max);
+                right.hit(ray, t_min, t_max).or(left_rec)
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox())
+    }
+}
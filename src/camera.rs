@@ -1,7 +1,39 @@
-use rand_distr::UnitSphere;
-
+use crate::sampler::{random_in_unit_disk, Sampler};
 use crate::*;
+
+// What a camera is built from, kept around to move the camera
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSettings {
+    pub look_from: glm::Vec3,
+    pub look_at: glm::Vec3,
+    pub vup: glm::Vec3,
+    // Vertical field of view in degrees
+    pub vfov: f32,
+    pub aspect_ratio: f32,
+    pub aperture: f32,
+    pub focus_dist: f32,
+}
+
+impl CameraSettings {
+    // The `[camera]` section of a scene file with these settings
+    pub fn to_toml(&self) -> String {
+        let vec3 = |v: &glm::Vec3| format!("[{:?}, {:?}, {:?}]", v.x, v.y, v.z);
+        format!(
+            "[camera]\nlook_from = {}\nlook_at = {}\nvup = {}\nvfov = {:?}\naperture = \
+             {:?}\nfocus_dist = {:?}\n",
+            vec3(&self.look_from),
+            vec3(&self.look_at),
+            vec3(&self.vup),
+            self.vfov,
+            self.aperture,
+            self.focus_dist,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
+    pub settings: CameraSettings,
     pub origin: glm::Vec3,
     pub lower_left_corner: glm::Vec3,
     pub horizontal: glm::Vec3,
@@ -23,6 +55,27 @@ impl Camera {
         aperture: f32,
         focus_dist: f32,
     ) -> Camera {
+        Camera::from_settings(CameraSettings {
+            look_from,
+            look_at,
+            vup,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+        })
+    }
+
+    pub fn from_settings(settings: CameraSettings) -> Camera {
+        let CameraSettings {
+            look_from,
+            look_at,
+            vup,
+            vfov,
+            aspect_ratio,
+            aperture,
+            focus_dist,
+        } = settings;
         let theta = vfov.to_radians();
         let h = (theta / 2.0).tan();
 
@@ -41,6 +94,7 @@ impl Camera {
 
         let lens_radius = aperture / 2.0;
         Camera {
+            settings,
             origin,
             lower_left_corner,
             horizontal,
@@ -51,8 +105,9 @@ impl Camera {
             lens_radius,
         }
     }
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk(s + t);
+    // The lens position comes from `sampler`
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(sampler);
         let offset = self.u * rd.x + self.v * rd.y;
         Ray::new(
             self.origin + offset,
@@ -60,8 +115,3 @@ impl Camera {
         )
     }
 }
-
-fn random_in_unit_disk(seed: f32) -> glm::Vec3 {
-    let r = &mut StdRng::seed_from_u64(f32_to_unique_u64(seed));
-    glm::make_vec3(&UnitSphere.sample(r)).normalize()
-}
@@ -0,0 +1,413 @@
+// Snapshots of a progressive render, to resume it after a crash or after
+// stopping it. Passes are deterministic, so a resumed render ends up with the
+// same image, bit for bit, as one that was never stopped.
+//
+// The file starts with a header (magic, hash of the settings and the scene,
+// size of the image, passes done) followed by the accumulation buffer of every
+// pixel, all little endian.
+//
+// Scenes are told apart by a `scene_id` given by the caller, anything that
+// changes when the scene does: the name of a built-in scene, the digest of a
+// scene file and of the models it loads...
+
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::filter::Filter;
+use crate::framebuffer::{Framebuffer, SampleStats};
+use crate::settings::RenderSettings;
+use crate::Color;
+
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    // Passes of the progressive render done so far
+    pub passes: usize,
+    // Covers the padded region of the render, the crop and the pixels around
+    // it whose samples reach it
+    pub framebuffer: Framebuffer,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(PathBuf, io::Error),
+    Invalid(PathBuf, String),
+}
+
+impl std::fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckpointError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            CheckpointError::Invalid(path, message) => {
+                write!(f, "{}: {message}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl Checkpoint {
+    // The start of a render, nothing done yet
+    pub fn new(settings: &RenderSettings) -> Self {
+        let region = settings.padded_region();
+        Self {
+            passes: 0,
+            framebuffer: Framebuffer::new(region.width, region.height),
+        }
+    }
+
+    // The pixels of the render, without the ones around the crop only
+    // rendered for the filter
+    pub fn cropped(&self, settings: &RenderSettings) -> Cow<'_, Framebuffer> {
+        let (region, padded) = (settings.region(), settings.padded_region());
+        if region == padded {
+            return Cow::Borrowed(&self.framebuffer);
+        }
+        Cow::Owned(self.framebuffer.crop(
+            region.x - padded.x,
+            region.y - padded.y,
+            region.width,
+            region.height,
+        ))
+    }
+
+    // Written to a temporary file first, so a crash while saving doesn't
+    // lose the previous checkpoint
+    pub fn save(
+        &self,
+        path: impl AsRef<Path>,
+        settings: &RenderSettings,
+        scene_id: &str,
+    ) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let temporary = path.with_extension("tmp");
+        let io_error = |err| CheckpointError::Io(path.to_owned(), err);
+        let mut file = BufWriter::new(File::create(&temporary).map_err(io_error)?);
+        self.write(&mut file, render_hash(settings, scene_id))
+            .and_then(|_| file.flush())
+            .map_err(io_error)?;
+        drop(file);
+        fs::rename(&temporary, path).map_err(io_error)
+    }
+
+    fn write(&self, file: &mut impl Write, hash: u64) -> io::Result<()> {
+        let framebuffer = &self.framebuffer;
+        file.write_all(MAGIC)?;
+        for value in [
+            hash,
+            framebuffer.width as u64,
+            framebuffer.height as u64,
+            self.passes as u64,
+        ] {
+            file.write_all(&value.to_le_bytes())?;
+        }
+        for index in 0..framebuffer.width * framebuffer.height {
+            let (sum, weight, stats) = framebuffer.pixel_data(index);
+            let floats = [sum.x, sum.y, sum.z, weight, stats.mean, stats.m2()];
+            for value in floats {
+                file.write_all(&value.to_le_bytes())?;
+            }
+            file.write_all(&stats.count.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Load a checkpoint, checking it was made with the same settings and
+    // scene
+    pub fn load(
+        path: impl AsRef<Path>,
+        settings: &RenderSettings,
+        scene_id: &str,
+    ) -> Result<Checkpoint, CheckpointError> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|err| CheckpointError::Io(path.to_owned(), err))?;
+        let hash = render_hash(settings, scene_id);
+        Self::read(&mut BufReader::new(file), settings, hash).map_err(|err| match err.kind() {
+            io::ErrorKind::InvalidData => {
+                CheckpointError::Invalid(path.to_owned(), err.to_string())
+            }
+            io::ErrorKind::UnexpectedEof => {
+                CheckpointError::Invalid(path.to_owned(), "truncated checkpoint".to_string())
+            }
+            _ => CheckpointError::Io(path.to_owned(), err),
+        })
+    }
+
+    fn read(file: &mut impl Read, settings: &RenderSettings, hash: u64) -> io::Result<Checkpoint> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut magic = [0; 8];
+        file.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a checkpoint"));
+        }
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            file.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let (file_hash, width, height, passes) =
+            (read_u64()?, read_u64()?, read_u64()?, read_u64()?);
+        let region = settings.padded_region();
+        if file_hash != hash || (width as usize, height as usize) != (region.width, region.height) {
+            return Err(invalid(
+                "the checkpoint was made with another scene or other render settings",
+            ));
+        }
+
+        let mut framebuffer = Framebuffer::new(region.width, region.height);
+        let mut bytes = [0; 4];
+        for index in 0..region.pixel_count() {
+            let mut floats = [0.0; 6];
+            for value in &mut floats {
+                file.read_exact(&mut bytes)?;
+                *value = f32::from_le_bytes(bytes);
+            }
+            file.read_exact(&mut bytes)?;
+            let [r, g, b, weight, mean, m2] = floats;
+            let stats = SampleStats::from_parts(u32::from_le_bytes(bytes), mean, m2);
+            framebuffer.set_pixel_data(index, Color::new(r, g, b), weight, stats);
+        }
+        Ok(Checkpoint {
+            passes: passes as usize,
+            framebuffer,
+        })
+    }
+}
+
+// FNV-1a, it stays the same from one run to another unlike the hasher of std
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+// Hash of everything that changes the result of a progressive render
+fn render_hash(settings: &RenderSettings, scene_id: &str) -> u64 {
+    let mut values = vec![
+        settings.width as u64,
+        settings.height as u64,
+        settings.samples_per_pixel as u64,
+        settings.max_depth as u64,
+        settings.seed,
+        settings.sampler as u64,
+    ];
+    let float = |value: f32| value.to_bits() as u64;
+    values.extend(match settings.filter {
+        Filter::Box { radius } => vec![0, float(radius)],
+        Filter::Tent { radius } => vec![1, float(radius)],
+        Filter::Gaussian { radius, sigma } => vec![2, float(radius), float(sigma)],
+        Filter::Mitchell { radius, b, c } => vec![3, float(radius), float(b), float(c)],
+        Filter::Lanczos { radius } => vec![4, float(radius)],
+    });
+    values.extend(match settings.adaptive {
+        Some(adaptive) => vec![
+            1,
+            adaptive.min_samples as u64,
+            adaptive.max_samples as u64,
+            float(adaptive.noise_threshold),
+        ],
+        None => vec![0],
+    });
+    values.extend(match settings.crop {
+        Some(crop) => vec![
+            1,
+            crop.x as u64,
+            crop.y as u64,
+            crop.width as u64,
+            crop.height as u64,
+        ],
+        None => vec![0],
+    });
+
+    let mut hasher = StableHasher::default();
+    for value in values {
+        hasher.write(&value.to_le_bytes());
+    }
+    hasher.write(scene_id.as_bytes());
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::hittable_list::HittableList;
+    use crate::material::{Lambertian, Metal};
+    use crate::sampler::SamplerKind;
+    use crate::settings::{AdaptiveSettings, Crop};
+    use crate::sky::Sky;
+    use crate::sphere::Sphere;
+    use crate::{Camera, Renderer, Scene};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray_tracing_{}_{name}", std::process::id()))
+    }
+
+    fn settings(filter: Filter, adaptive: bool) -> RenderSettings {
+        RenderSettings {
+            width: 36,
+            height: 34,
+            samples_per_pixel: 6,
+            max_depth: 4,
+            filter,
+            // The blue noise takes a while to generate in debug builds
+            sampler: SamplerKind::Independent,
+            adaptive: adaptive.then_some(AdaptiveSettings {
+                min_samples: 4,
+                max_samples: 12,
+                noise_threshold: 0.05,
+            }),
+            ..RenderSettings::default()
+        }
+    }
+
+    // A diffuse and a metal ball on the ground, small enough for debug builds
+    fn scene(settings: &RenderSettings) -> Scene {
+        let mut world = HittableList::default();
+        let ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Sphere::new(glm::vec3(0.0, -100.5, -1.0), 100.0, ground));
+        let diffuse = Arc::new(Lambertian::new(Color::new(0.7, 0.3, 0.3)));
+        world.add(Sphere::new(glm::vec3(-0.5, 0.0, -1.0), 0.5, diffuse));
+        let metal = Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 0.2));
+        world.add(Sphere::new(glm::vec3(0.6, 0.0, -1.2), 0.5, metal));
+        let camera = Camera::new(
+            glm::vec3(0.0, 0.5, 1.0),
+            glm::vec3(0.0, 0.0, -1.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            50.0,
+            settings.aspect_ratio(),
+            0.0,
+            2.0,
+        );
+        let sky = Sky::Gradient {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        };
+        Scene::new(world, camera, sky)
+    }
+
+    // The file contents, to compare checkpoints bit for bit
+    fn bytes(checkpoint: &Checkpoint) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes, 0).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resumed_render_is_identical() {
+        let path = temp_path("resumed.ckpt");
+        let scene = scene(&settings(Filter::BOX, false));
+        for filter in [Filter::BOX, Filter::GAUSSIAN, Filter::LANCZOS] {
+            for adaptive in [false, true] {
+                let settings = settings(filter, adaptive);
+                let renderer = Renderer::new(settings.clone());
+                let start = Checkpoint::new(&settings);
+                let full = renderer.resume_progressive(&scene, start.clone(), |_, _| true);
+
+                let stopped = renderer.resume_progressive(&scene, start, |checkpoint, _| {
+                    checkpoint.is_none_or(|checkpoint| checkpoint.passes < 3)
+                });
+                assert_eq!(stopped.passes, 3);
+                stopped.save(&path, &settings, "balls").unwrap();
+                let loaded = Checkpoint::load(&path, &settings, "balls").unwrap();
+                assert_eq!(bytes(&loaded), bytes(&stopped));
+                let resumed = renderer.resume_progressive(&scene, loaded, |_, _| true);
+
+                assert_eq!(
+                    resumed.passes, full.passes,
+                    "{filter:?} adaptive: {adaptive}"
+                );
+                assert!(
+                    bytes(&resumed) == bytes(&full),
+                    "{filter:?} adaptive: {adaptive}"
+                );
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_other_renders() {
+        let path = temp_path("other.ckpt");
+        let settings = settings(Filter::GAUSSIAN, false);
+        Checkpoint::new(&settings)
+            .save(&path, &settings, "book")
+            .unwrap();
+        assert!(Checkpoint::load(&path, &settings, "book").is_ok());
+
+        let crop = Crop {
+            x: 4,
+            y: 4,
+            width: 8,
+            height: 8,
+        };
+        let others = [
+            (settings.clone(), "custom"),
+            (
+                RenderSettings {
+                    seed: 7,
+                    ..settings.clone()
+                },
+                "book",
+            ),
+            (
+                RenderSettings {
+                    width: 37,
+                    ..settings.clone()
+                },
+                "book",
+            ),
+            (
+                RenderSettings {
+                    crop: Some(crop),
+                    ..settings.clone()
+                },
+                "book",
+            ),
+        ];
+        for (other, scene_id) in &others {
+            let result = Checkpoint::load(&path, other, scene_id);
+            assert!(
+                matches!(result, Err(CheckpointError::Invalid(..))),
+                "{scene_id} {other:?}"
+            );
+        }
+
+        // Files with the right size but another hash, and the other way
+        // around
+        let hash = render_hash(&settings, "book");
+        let region = settings.padded_region();
+        let wider = Checkpoint {
+            passes: 0,
+            framebuffer: Framebuffer::new(region.width + 1, region.height),
+        };
+        for (checkpoint, hash) in [(Checkpoint::new(&settings), hash ^ 1), (wider, hash)] {
+            let mut bytes = Vec::new();
+            checkpoint.write(&mut bytes, hash).unwrap();
+            fs::write(&path, bytes).unwrap();
+            let result = Checkpoint::load(&path, &settings, "book");
+            assert!(matches!(result, Err(CheckpointError::Invalid(..))));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
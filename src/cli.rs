@@ -0,0 +1,415 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use ray_tracing_in_one_we::tonemap::{self, ToneMapping};
+use ray_tracing_in_one_we::{
+    AdaptiveSettings, ColorSpace, Crop, Filter, OutputFormat, OutputOptions, RenderSettings,
+    SamplerKind, TraversalOrder,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "A ray tracer from Ray Tracing in One Weekend, and more")]
+pub struct Cli {
+    /// Built-in scene (`book` or `custom`) or path to a TOML scene file
+    #[arg(short, long, default_value = "book")]
+    pub scene: SceneChoice,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = at_least_2)]
+    pub width: Option<usize>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = at_least_2)]
+    pub height: Option<usize>,
+
+    /// Samples per pixel
+    #[arg(long, value_parser = at_least_1)]
+    pub spp: Option<usize>,
+
+    /// Stop sampling pixels once their noise is below --noise-threshold,
+    /// taking between --min-spp and --max-spp samples
+    #[arg(long)]
+    pub adaptive: bool,
+
+    /// Noise left in converged pixels in adaptive mode, as the standard error
+    /// of their gamma encoded luminance
+    #[arg(long, requires = "adaptive", default_value_t = 0.01, value_parser = positive)]
+    pub noise_threshold: f32,
+
+    /// Samples taken in every pixel in adaptive mode
+    #[arg(long, requires = "adaptive", default_value_t = 16, value_parser = at_least_1)]
+    pub min_spp: usize,
+
+    /// Most samples taken in a pixel in adaptive mode. Defaults to --spp
+    #[arg(long, requires = "adaptive", value_parser = at_least_1)]
+    pub max_spp: Option<usize>,
+
+    /// Maximum number of bounces of a ray
+    #[arg(long, value_parser = at_least_1)]
+    pub max_depth: Option<usize>,
+
+    /// Seed used to generate the scene and the sampling pattern
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// How the random numbers of the samples are spread. Defaults to blue
+    /// noise pixel positions and independent random numbers for the rest
+    #[arg(long)]
+    pub sampler: Option<SamplerArg>,
+
+    /// Reconstruction filter weighting the samples of neighboring pixels.
+    /// Defaults to `box`, every sample only counting for its own pixel
+    #[arg(long)]
+    pub filter: Option<FilterArg>,
+
+    /// Radius of the reconstruction filter in pixels. Each filter has its
+    /// own default
+    #[arg(long, value_parser = positive)]
+    pub filter_radius: Option<f32>,
+
+    /// Order in which the tiles of the image are rendered. Defaults to
+    /// `shuffled`, filling the whole image in evenly
+    #[arg(long)]
+    pub order: Option<OrderArg>,
+
+    /// Only render the rectangle of the image at X,Y (top left corner) of
+    /// WIDTHxHEIGHT pixels, e.g. `400,300,200x100`. The output image is just
+    /// that rectangle unless --composite is given
+    #[arg(long, value_parser = crop, value_name = "X,Y,WIDTHxHEIGHT")]
+    pub crop: Option<Crop>,
+
+    /// Paste the --crop into this full frame image, saved before in the same
+    /// format and with the same options. The output path defaults to it
+    #[arg(long, requires = "crop")]
+    pub composite: Option<PathBuf>,
+
+    /// Output image path. Defaults to the one of the scene
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output image format. Defaults to the one of the output extension
+    #[arg(short, long)]
+    pub format: Option<FormatArg>,
+
+    /// Write EXR images with 16 bits floats instead of 32 bits
+    #[arg(long)]
+    pub half_float: bool,
+
+    /// Tone mapping operator of 8 bits images and of the window
+    #[arg(short, long, default_value = "clamp")]
+    pub tone_map: ToneMapArg,
+
+    /// Exposure in stops, applied before tone mapping
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub exposure: f32,
+
+    /// Luminance mapped to white by the `reinhard-extended` operator
+    #[arg(long, default_value_t = 4.0, value_parser = positive)]
+    pub white: f32,
+
+    /// Color space of the output image. Rendering always happens in linear
+    /// Rec.709, the primaries of sRGB
+    #[arg(long, default_value = "srgb")]
+    pub color_space: ColorSpaceArg,
+
+    /// Store linear values in 8 bits images instead of gamma encoded ones
+    #[arg(long)]
+    pub linear: bool,
+
+    /// Number of rendering threads. Defaults to the number of cores
+    #[arg(short = 'j', long, value_parser = at_least_1)]
+    pub threads: Option<usize>,
+
+    /// Render the whole image one sample per pixel at a time, refining it
+    /// until it has all its samples. Closing the window early still saves
+    /// the image
+    #[arg(long)]
+    pub progressive: bool,
+
+    /// Move the camera in the window with the keyboard and the mouse, the
+    /// render starts over after each move. Implies --progressive
+    #[arg(long, conflicts_with_all = ["checkpoint", "headless"])]
+    pub interactive: bool,
+
+    /// Save the render to this file between passes, every
+    /// --checkpoint-interval seconds and when it's stopped. Implies
+    /// --progressive
+    #[arg(long)]
+    pub checkpoint: Option<PathBuf>,
+
+    /// Seconds between two checkpoints
+    #[arg(long, default_value_t = 60, value_parser = at_least_1)]
+    pub checkpoint_interval: usize,
+
+    /// Continue the render saved in the --checkpoint file. The result is the
+    /// same as if it was never stopped
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// Render without opening a window
+    #[arg(long)]
+    pub headless: bool,
+}
+
+#[derive(Debug, Clone)]
+pub enum SceneChoice {
+    // The scene of the book, in scene.rs
+    Book,
+    // The scene in my_scene.rs
+    Custom,
+    File(PathBuf),
+}
+
+impl std::str::FromStr for SceneChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "book" => Ok(SceneChoice::Book),
+            "custom" => Ok(SceneChoice::Custom),
+            _ if s.ends_with(".toml") => Ok(SceneChoice::File(s.into())),
+            _ => Err(format!(
+                "`{s}` is neither a built-in scene (book, custom) nor a .toml scene file"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SamplerArg {
+    BlueNoise,
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl From<SamplerArg> for SamplerKind {
+    fn from(sampler: SamplerArg) -> Self {
+        match sampler {
+            SamplerArg::BlueNoise => SamplerKind::BlueNoise,
+            SamplerArg::Independent => SamplerKind::Independent,
+            SamplerArg::Stratified => SamplerKind::Stratified,
+            SamplerArg::Halton => SamplerKind::Halton,
+            SamplerArg::Sobol => SamplerKind::Sobol,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OrderArg {
+    Hilbert,
+    Morton,
+    Spiral,
+    Scanline,
+    Shuffled,
+}
+
+impl From<OrderArg> for TraversalOrder {
+    fn from(order: OrderArg) -> Self {
+        match order {
+            OrderArg::Hilbert => TraversalOrder::Hilbert,
+            OrderArg::Morton => TraversalOrder::Morton,
+            OrderArg::Spiral => TraversalOrder::Spiral,
+            OrderArg::Scanline => TraversalOrder::Scanline,
+            OrderArg::Shuffled => TraversalOrder::Shuffled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FilterArg {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl From<FilterArg> for Filter {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            FilterArg::Box => Filter::BOX,
+            FilterArg::Tent => Filter::TENT,
+            FilterArg::Gaussian => Filter::GAUSSIAN,
+            FilterArg::Mitchell => Filter::MITCHELL,
+            FilterArg::Lanczos => Filter::LANCZOS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FormatArg {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Tiff,
+    Exr,
+    Hdr,
+    Pfm,
+}
+
+impl From<FormatArg> for OutputFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Png => OutputFormat::Png,
+            FormatArg::Jpeg => OutputFormat::Jpeg,
+            FormatArg::Bmp => OutputFormat::Bmp,
+            FormatArg::Tga => OutputFormat::Tga,
+            FormatArg::Tiff => OutputFormat::Tiff,
+            FormatArg::Exr => OutputFormat::Exr { half_float: false },
+            FormatArg::Hdr => OutputFormat::Hdr,
+            FormatArg::Pfm => OutputFormat::Pfm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ToneMapArg {
+    Clamp,
+    Reinhard,
+    ReinhardExtended,
+    Aces,
+    Hable,
+    Agx,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ColorSpaceArg {
+    #[value(alias = "rec709")]
+    Srgb,
+    #[value(name = "p3")]
+    DisplayP3,
+    Rec2020,
+}
+
+impl From<ColorSpaceArg> for ColorSpace {
+    fn from(color_space: ColorSpaceArg) -> Self {
+        match color_space {
+            ColorSpaceArg::Srgb => ColorSpace::Rec709,
+            ColorSpaceArg::DisplayP3 => ColorSpace::DisplayP3,
+            ColorSpaceArg::Rec2020 => ColorSpace::Rec2020,
+        }
+    }
+}
+
+impl Cli {
+    // Override the settings of the scene with the ones given on the command line
+    pub fn apply(&self, settings: &mut RenderSettings) {
+        settings.width = self.width.unwrap_or(settings.width);
+        settings.height = self.height.unwrap_or(settings.height);
+        settings.samples_per_pixel = self.spp.unwrap_or(settings.samples_per_pixel);
+        settings.max_depth = self.max_depth.unwrap_or(settings.max_depth);
+        settings.seed = self.seed.unwrap_or(settings.seed);
+        if self.adaptive {
+            let max_samples = self.max_spp.unwrap_or(settings.samples_per_pixel);
+            settings.adaptive = Some(AdaptiveSettings {
+                min_samples: self.min_spp.min(max_samples),
+                max_samples,
+                noise_threshold: self.noise_threshold,
+            });
+        }
+        if let Some(sampler) = self.sampler {
+            settings.sampler = sampler.into();
+        }
+        if let Some(filter) = self.filter {
+            settings.filter = filter.into();
+        }
+        if let Some(radius) = self.filter_radius {
+            settings.filter = settings.filter.with_radius(radius);
+        }
+        if let Some(order) = self.order {
+            settings.order = order.into();
+        }
+        if self.crop.is_some() {
+            settings.crop = self.crop;
+        }
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        let exposure = self.exposure;
+        match self.tone_map {
+            ToneMapArg::Clamp => ToneMapping::new(exposure, tonemap::Clamp),
+            ToneMapArg::Reinhard => ToneMapping::new(exposure, tonemap::Reinhard),
+            ToneMapArg::ReinhardExtended => {
+                ToneMapping::new(exposure, tonemap::ExtendedReinhard { white: self.white })
+            }
+            ToneMapArg::Aces => ToneMapping::new(exposure, tonemap::Aces),
+            ToneMapArg::Hable => ToneMapping::new(exposure, tonemap::Hable),
+            ToneMapArg::Agx => ToneMapping::new(exposure, tonemap::AgX),
+        }
+    }
+
+    pub fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            tone_mapping: self.tone_mapping(),
+            color_space: self.color_space.into(),
+            linear: self.linear,
+        }
+    }
+
+    // The format to save the image with, either given explicitly or guessed
+    // from the extension
+    pub fn output_format(&self, path: &std::path::Path) -> Result<OutputFormat, String> {
+        let format = match self.format {
+            Some(format) => format.into(),
+            None => OutputFormat::from_path(path).ok_or_else(|| {
+                format!(
+                    "can't guess the image format of `{}`, use an extension like .png or pass \
+                     --format",
+                    path.display()
+                )
+            })?,
+        };
+        match format {
+            OutputFormat::Exr { .. } => Ok(OutputFormat::Exr {
+                half_float: self.half_float,
+            }),
+            _ if self.half_float => Err("--half-float only applies to EXR images".to_string()),
+            format => Ok(format),
+        }
+    }
+}
+
+fn at_least(s: &str, min: usize) -> Result<usize, String> {
+    let value: usize = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if value < min {
+        return Err(format!("must be at least {min}"));
+    }
+    Ok(value)
+}
+
+fn at_least_1(s: &str) -> Result<usize, String> {
+    at_least(s, 1)
+}
+
+fn at_least_2(s: &str) -> Result<usize, String> {
+    at_least(s, 2)
+}
+
+fn crop(s: &str) -> Result<Crop, String> {
+    let invalid = || format!("`{s}` is not of the form X,Y,WIDTHxHEIGHT");
+    let (x, rest) = s.split_once(',').ok_or_else(invalid)?;
+    let (y, size) = rest.split_once(',').ok_or_else(invalid)?;
+    let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+    let parse = |value: &str| value.trim().parse::<usize>().map_err(|_| invalid());
+    let crop = Crop {
+        x: parse(x)?,
+        y: parse(y)?,
+        width: parse(width)?,
+        height: parse(height)?,
+    };
+    if crop.width == 0 || crop.height == 0 {
+        return Err("the crop must not be empty".to_string());
+    }
+    Ok(crop)
+}
+
+fn positive(s: &str) -> Result<f32, String> {
+    let value: f32 = s.parse().map_err(|_| format!("`{s}` is not a number"))?;
+    if value.is_nan() || value <= 0.0 {
+        return Err("must be positive".to_string());
+    }
+    Ok(value)
+}
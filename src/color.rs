@@ -0,0 +1,88 @@
+// Color spaces of the output images. The renderer itself works in linear
+// Rec.709, the primaries and white point of sRGB: scene colors, materials and
+// the framebuffer are all in that space.
+
+use crate::Color;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorSpace {
+    // sRGB, what most screens and tools expect
+    #[default]
+    Rec709,
+    // Wide gamut screens, like recent Apple devices
+    DisplayP3,
+    // UHD television
+    Rec2020,
+}
+
+impl ColorSpace {
+    // Convert a linear Rec.709 color to linear values in this space
+    pub fn from_rec709(&self, color: Color) -> Color {
+        match self {
+            ColorSpace::Rec709 => color,
+            ColorSpace::DisplayP3 => Self::rec709_to_p3() * color,
+            ColorSpace::Rec2020 => Self::rec709_to_rec2020() * color,
+        }
+    }
+
+    // The transfer function of the space, turning a linear value between 0
+    // and 1 into the non linear one stored in 8 bits images
+    pub fn encode(&self, c: f32) -> f32 {
+        match self {
+            ColorSpace::Rec709 | ColorSpace::DisplayP3 => srgb_oetf(c),
+            ColorSpace::Rec2020 => rec2020_oetf(c),
+        }
+    }
+
+    // CIE xy chromaticities of the red, green and blue primaries. They all
+    // use the D65 white point
+    pub fn primaries(&self) -> [(f32, f32); 3] {
+        match self {
+            ColorSpace::Rec709 => [(0.64, 0.33), (0.30, 0.60), (0.15, 0.06)],
+            ColorSpace::DisplayP3 => [(0.680, 0.320), (0.265, 0.690), (0.150, 0.060)],
+            ColorSpace::Rec2020 => [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+        }
+    }
+
+    pub const WHITE_POINT: (f32, f32) = (0.3127, 0.3290);
+
+    #[rustfmt::skip]
+    fn rec709_to_p3() -> glm::Mat3 {
+        glm::mat3(
+            0.8224621, 0.177538, 0.0,
+            0.0331941, 0.9668058, 0.0,
+            0.0170827, 0.0723974, 0.9105199,
+        )
+    }
+
+    #[rustfmt::skip]
+    fn rec709_to_rec2020() -> glm::Mat3 {
+        glm::mat3(
+            0.627404, 0.329282, 0.0433136,
+            0.069097, 0.91954, 0.0113612,
+            0.0163916, 0.0880132, 0.895595,
+        )
+    }
+}
+
+// Relative luminance of a linear Rec.709 color
+pub fn luminance(color: &Color) -> f32 {
+    color.dot(&Color::new(0.2126, 0.7152, 0.0722))
+}
+
+// The piecewise sRGB curve, a bit different from a plain 2.2 gamma near black
+pub fn srgb_oetf(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn rec2020_oetf(c: f32) -> f32 {
+    if c < 0.018053968 {
+        4.5 * c
+    } else {
+        1.0992968 * c.powf(0.45) - 0.0992968
+    }
+}
@@ -0,0 +1,124 @@
+// Reconstruction filters. Every sample is splatted onto the pixels around it,
+// weighted by the filter at its distance to their center, and each pixel is
+// the weighted average of the samples it received.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    // Every sample counts only for the pixel it's in
+    Box { radius: f32 },
+    // Weight falling linearly to 0 at the radius
+    Tent { radius: f32 },
+    Gaussian { radius: f32, sigma: f32 },
+    // Mitchell-Netravali cubic, `b` and `c` trade blurring for ringing
+    Mitchell { radius: f32, b: f32, c: f32 },
+    // Windowed sinc, the sharpest of them but it can ring around edges
+    Lanczos { radius: f32 },
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::BOX
+    }
+}
+
+impl Filter {
+    // Usual parameters of each filter
+    pub const BOX: Filter = Filter::Box { radius: 0.5 };
+    pub const TENT: Filter = Filter::Tent { radius: 1.0 };
+    pub const GAUSSIAN: Filter = Filter::Gaussian {
+        radius: 1.5,
+        sigma: 0.5,
+    };
+    pub const MITCHELL: Filter = Filter::Mitchell {
+        radius: 2.0,
+        b: 1.0 / 3.0,
+        c: 1.0 / 3.0,
+    };
+    pub const LANCZOS: Filter = Filter::Lanczos { radius: 3.0 };
+
+    // Distance from the center of a pixel past which samples don't count
+    pub fn radius(&self) -> f32 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius,
+        }
+    }
+
+    // How many pixels away from its own pixel a sample can count. Samples are
+    // at most half a pixel away from the center of their pixel
+    pub fn reach(&self) -> usize {
+        ((self.radius() + 0.5).ceil() as usize).saturating_sub(1)
+    }
+
+    // The same filter with another radius
+    pub fn with_radius(mut self, new_radius: f32) -> Self {
+        match &mut self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => *radius = new_radius,
+        }
+        self
+    }
+
+    // Weight of a sample at `(x, y)` pixels from the center of a pixel. All
+    // the filters are separable
+    pub fn weight(&self, x: f32, y: f32) -> f32 {
+        self.weight_1d(x) * self.weight_1d(y)
+    }
+
+    pub fn weight_1d(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match *self {
+            Filter::Box { radius } => {
+                if x <= radius {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Tent { radius } => (radius - x).max(0.0),
+            Filter::Gaussian { radius, sigma } => {
+                let gaussian = |x: f32| (-x * x / (2.0 * sigma * sigma)).exp();
+                // Shifted down so the weight reaches 0 at the radius
+                (gaussian(x) - gaussian(radius)).max(0.0)
+            }
+            Filter::Mitchell { radius, b, c } => {
+                if x >= radius {
+                    return 0.0;
+                }
+                // The cubic is defined over [-2, 2]
+                let x = 2.0 * x / radius;
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                };
+                weight / 6.0
+            }
+            Filter::Lanczos { radius } => {
+                if x >= radius {
+                    return 0.0;
+                }
+                sinc(x) * sinc(x / radius)
+            }
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x < 1e-5 {
+        return 1.0;
+    }
+    let x = x * std::f32::consts::PI;
+    x.sin() / x
+}
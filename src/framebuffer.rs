@@ -0,0 +1,185 @@
+use crate::output::{ExtraChannel, Image};
+use crate::Color;
+
+// Accumulates the unclamped radiance of every sample, pixel by pixel. Samples
+// are weighted by the reconstruction filter and the weighted average is only
+// taken when resolving the buffer into an image
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+    // Samples taken inside each pixel, whatever pixels they were splatted on
+    stats: Vec<SampleStats>,
+}
+
+// Running mean and variance of the luminance of the samples of a pixel, with
+// Welford's algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SampleStats {
+    pub count: u32,
+    pub mean: f32,
+    // Sum of the squared differences to the mean
+    m2: f32,
+}
+
+impl SampleStats {
+    pub fn from_parts(count: u32, mean: f32, m2: f32) -> Self {
+        Self { count, mean, m2 }
+    }
+
+    pub fn m2(&self) -> f32 {
+        self.m2
+    }
+
+    pub fn add(&mut self, value: f32) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    // Combine the stats of two sets of samples
+    pub fn merge(&mut self, other: &SampleStats) {
+        if other.count == 0 {
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f32 / count as f32;
+        self.m2 += other.m2 + delta * delta * self.count as f32 * other.count as f32 / count as f32;
+        self.count = count;
+    }
+
+    // Unbiased variance of the samples
+    pub fn variance(&self) -> f32 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / (self.count - 1) as f32
+    }
+
+    // How far the mean is likely to be from the converged value, measured
+    // after a gamma 2 encoding so dark pixels need to be as precise as they
+    // look
+    pub fn encoded_error(&self) -> f32 {
+        let standard_error = (self.variance() / self.count as f32).sqrt();
+        standard_error / (2.0 * self.mean.max(1e-4).sqrt())
+    }
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sums: vec![Color::zeros(); width * height],
+            weights: vec![0.0; width * height],
+            stats: vec![SampleStats::default(); width * height],
+        }
+    }
+
+    // Add samples whose weighted radiance sums up to `sum` and whose weights
+    // sum up to `weight` to a pixel
+    pub fn splat(&mut self, index: usize, sum: Color, weight: f32) {
+        self.sums[index] += sum;
+        self.weights[index] += weight;
+    }
+
+    // Add the stats of samples taken inside a pixel
+    pub fn add_stats(&mut self, index: usize, stats: &SampleStats) {
+        self.stats[index].merge(stats);
+    }
+
+    pub fn stats(&self, index: usize) -> &SampleStats {
+        &self.stats[index]
+    }
+
+    // Everything accumulated in a pixel: weighted sum, sum of the weights and
+    // stats of the samples
+    pub fn pixel_data(&self, index: usize) -> (Color, f32, SampleStats) {
+        (self.sums[index], self.weights[index], self.stats[index])
+    }
+
+    pub fn set_pixel_data(&mut self, index: usize, sum: Color, weight: f32, stats: SampleStats) {
+        self.sums[index] = sum;
+        self.weights[index] = weight;
+        self.stats[index] = stats;
+    }
+
+    // Add everything accumulated in `other` to this buffer, with the top left
+    // corner of `other` at `(x, y)`. What falls outside is dropped
+    pub fn merge(&mut self, other: &Framebuffer, x: isize, y: isize) {
+        for other_y in 0..other.height {
+            let Ok(target_y) = usize::try_from(y + other_y as isize) else {
+                continue;
+            };
+            if target_y >= self.height {
+                break;
+            }
+            for other_x in 0..other.width {
+                let Ok(target_x) = usize::try_from(x + other_x as isize) else {
+                    continue;
+                };
+                if target_x >= self.width {
+                    break;
+                }
+                let (index, other_index) = (
+                    target_x + target_y * self.width,
+                    other_x + other_y * other.width,
+                );
+                self.sums[index] += other.sums[other_index];
+                self.weights[index] += other.weights[other_index];
+                self.stats[index].merge(&other.stats[other_index]);
+            }
+        }
+    }
+
+    // Copy of the `width` × `height` pixels with their top left corner at
+    // `(x, y)`
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Framebuffer {
+        let mut cropped = Framebuffer::new(width, height);
+        for row in 0..height {
+            let (start, cropped_start) = (x + (y + row) * self.width, row * width);
+            let (range, cropped_range) =
+                (start..start + width, cropped_start..cropped_start + width);
+            cropped.sums[cropped_range.clone()].copy_from_slice(&self.sums[range.clone()]);
+            cropped.weights[cropped_range.clone()].copy_from_slice(&self.weights[range.clone()]);
+            cropped.stats[cropped_range].copy_from_slice(&self.stats[range]);
+        }
+        cropped
+    }
+
+    pub fn sample_count(&self, index: usize) -> u32 {
+        self.stats[index].count
+    }
+
+    // Weighted average radiance of a pixel, black if it has no samples yet
+    pub fn mean(&self, index: usize) -> Color {
+        let weight = self.weights[index];
+        if weight > 0.0 {
+            self.sums[index] / weight
+        } else {
+            Color::zeros()
+        }
+    }
+
+    pub fn to_image(&self) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: (0..self.sums.len()).map(|i| self.mean(i)).collect(),
+            extra_channels: vec![
+                ExtraChannel {
+                    name: "samples".to_string(),
+                    values: self.stats.iter().map(|stats| stats.count as f32).collect(),
+                },
+                ExtraChannel {
+                    name: "variance".to_string(),
+                    values: self.stats.iter().map(SampleStats::variance).collect(),
+                },
+            ],
+        }
+    }
+}
@@ -1,15 +1,20 @@
+use crate::aabb::Aabb;
 use crate::material::MaterialObject;
 use crate::ray::Ray;
 
 // A trait for every object that can be "hitted" by a ray (i.e. seen on screen)
 pub trait Hittable {
     fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+    // The box enclosing the object, or None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
 }
 
 // A struct that keeps informations about a hit point
 pub struct HitRecord {
     pub point: glm::Vec3,
     pub t: f32,
+    // Surface coordinates of the hit point
+    pub uv: glm::Vec2,
     pub normal: glm::Vec3,
     pub front_face: bool,
     pub material: MaterialObject,
@@ -19,6 +24,7 @@ impl HitRecord {
     pub fn new_with_front_face(
         point: glm::Vec3,
         t: f32,
+        uv: glm::Vec2,
         material: MaterialObject,
         ray: &Ray,
         outward_normal: &glm::Vec3,
@@ -32,9 +38,21 @@ impl HitRecord {
         HitRecord {
             point,
             t,
+            uv,
             material,
             front_face,
             normal,
         }
     }
+
+    // Replace the geometric normal by an interpolated one, kept on the same
+    // side of the surface as the ray
+    pub fn set_shading_normal(&mut self, shading_normal: &glm::Vec3) {
+        let shading_normal = shading_normal.normalize();
+        self.normal = if shading_normal.dot(&self.normal) < 0.0 {
+            -shading_normal
+        } else {
+            shading_normal
+        };
+    }
 }
@@ -1,6 +1,7 @@
+use crate::aabb::Aabb;
 use crate::hittable::{HitRecord, Hittable};
 
-type HittableObject = Box<dyn Hittable + Send + Sync>;
+pub type HittableObject = Box<dyn Hittable + Send + Sync>;
 
 #[derive(Default)]
 pub struct HittableList {
@@ -36,4 +37,10 @@ impl Hittable for HittableList {
 
         output_rec
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|object| object.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, b| Some(acc.surrounding(&b?)))
+    }
 }
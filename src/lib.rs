@@ -0,0 +1,57 @@
+// A ray tracer following Peter Shirley's Ray Tracing in One Weekend. Build a
+// `Scene`, from Rust code or a scene file, and give it to `render` or a
+// `Renderer` to get an `Image`.
+
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod filter;
+pub mod framebuffer;
+pub mod hittable;
+pub mod hittable_list;
+pub mod material;
+pub mod mesh;
+pub mod my_scene;
+mod noise;
+pub mod obj;
+pub mod output;
+pub mod ray;
+pub mod renderer;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod settings;
+pub mod sky;
+pub mod space_filler;
+pub mod sphere;
+pub mod tile;
+pub mod tonemap;
+pub mod triangle;
+
+use std::sync::Arc;
+
+pub use camera::{Camera, CameraSettings};
+pub use checkpoint::Checkpoint;
+pub use color::ColorSpace;
+pub use filter::Filter;
+pub use framebuffer::{Framebuffer, SampleStats};
+pub use hittable::Hittable;
+use hittable_list::HittableList;
+pub use material::Material;
+pub use output::{Image, OutputFormat, OutputOptions};
+use ray::Ray;
+pub use renderer::{render, Renderer, Scene};
+pub use sampler::{Sampler, SamplerKind};
+pub use settings::{AdaptiveSettings, Crop, RenderSettings};
+pub use space_filler::TraversalOrder;
+pub use tonemap::{ToneMapper, ToneMapping};
+extern crate nalgebra_glm as glm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Set a seed to get reproductible results every time
+pub const SEED: u64 = 123;
+
+pub type Color = glm::Vec3;
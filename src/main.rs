@@ -1,205 +1,323 @@
-mod camera;
-mod hittable;
-mod hittable_list;
-mod material;
-mod my_scene;
-mod noise;
-mod ray;
-mod scene;
-mod space_filler;
-mod sphere;
+mod cli;
+mod preview;
 
 use std::io::{stdout, Write};
-use std::sync::{mpsc, Arc};
-use std::{env, path, thread};
-
-use hittable_list::HittableList;
-use minifb::{Key, Window, WindowOptions};
-// use my_scene::*;
-use noise::*;
-use ray::Ray;
-use scene::*;
-
-use crate::camera::Camera;
-use crate::hittable::Hittable;
-extern crate nalgebra_glm as glm;
-use image::{Rgb, RgbImage};
-use once_cell::sync::{Lazy, OnceCell};
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
-use rand_distr::{Distribution, UnitBall};
-use rayon::prelude::*;
-
-use crate::material::ScatterResponse;
-
-// Util function for minifb because it takes a specially formatted u32 for
-// colors
-const fn from_u8_0rgb(r: u8, g: u8, b: u8) -> u32 {
-    let (r, g, b) = (r as u32, g as u32, b as u32);
-    (r << 16) | (g << 8) | b
-}
+use std::time::{Duration, Instant};
+use std::{fs, mem, path};
 
-// Set a seed to get reproductible results every time
-pub const SEED: u64 = 123;
+use clap::Parser;
+use cli::{Cli, SceneChoice};
+use preview::Preview;
+use ray_tracing_in_one_we::{
+    my_scene, scene, scene_file, Camera, Checkpoint, Framebuffer, Image, OutputFormat,
+    RenderSettings, Renderer, Scene,
+};
 
-type Color = glm::Vec3;
+// Time between two progress reports
+const PREVIEW_INTERVAL: Duration = Duration::from_millis(33);
+const HEADLESS_INTERVAL: Duration = Duration::from_secs(1);
 
-static WORLD: OnceCell<HittableList> = OnceCell::new();
-static CAMERA: OnceCell<Camera> = OnceCell::new();
+fn main() {
+    let now = Instant::now();
+    let cli = Cli::parse();
+    if let Some(threads) = cli.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("The thread pool is only built once");
+    }
 
-const UPDATE_RATE: u64 = 30; // FPS
-const MICRO_BETWEEN_FRAME: u64 = 1_000_000 / UPDATE_RATE;
+    // The scene id tells scenes apart in checkpoints. Built-in scenes only
+    // depend on the seed, which is part of the settings
+    let (settings, mut scene, default_path, scene_id) = match &cli.scene {
+        SceneChoice::Book => {
+            let mut settings = scene::render_settings();
+            cli.apply(&mut settings);
+            let scene = scene::build_scene(&settings);
+            (
+                settings,
+                scene,
+                scene::OUTPUT_NAME.into(),
+                "book".to_string(),
+            )
+        }
+        SceneChoice::Custom => {
+            let mut settings = my_scene::render_settings();
+            cli.apply(&mut settings);
+            let scene = my_scene::build_scene(&settings);
+            (
+                settings,
+                scene,
+                my_scene::OUTPUT_NAME.into(),
+                "custom".to_string(),
+            )
+        }
+        SceneChoice::File(path) => load_scene_file(path, &cli),
+    };
+    if let Some(crop) = settings.crop {
+        if !crop.fits_in(settings.width, settings.height) {
+            exit_with_error(&format!(
+                "the crop doesn't fit in the {}x{} image",
+                settings.width, settings.height
+            ));
+        }
+    }
+    let save_path = cli
+        .output
+        .clone()
+        .or_else(|| cli.composite.clone())
+        .unwrap_or(default_path);
+    let format = cli
+        .output_format(&save_path)
+        .unwrap_or_else(|err| exit_with_error(&err));
+    let options = cli.output_options();
+    if let Some(base) = &cli.composite {
+        let base_format = OutputFormat::from_path(base).map(|base| mem::discriminant(&base));
+        if base_format != Some(mem::discriminant(&format)) {
+            exit_with_error(&format!(
+                "{} isn't in the format of the output image",
+                base.display()
+            ));
+        }
+        // Checked now rather than after the whole render
+        match Image::dimensions(base, format) {
+            Ok(size) if size == (settings.width, settings.height) => {}
+            Ok((width, height)) => exit_with_error(&format!(
+                "{} is {width}x{height}, the render is {}x{}",
+                base.display(),
+                settings.width,
+                settings.height
+            )),
+            Err(err) => exit_with_error(&format!("can't read {}: {err}", base.display())),
+        }
+    }
 
-fn main() {
-    let now = std::time::Instant::now();
-    let args: Vec<String> = env::args().collect();
-    let default_path = OUTPUT_NAME.to_string();
-    let save_path = args.get(1).unwrap_or(&default_path);
-    let mut buffer = vec![0u32; WIDTH * HEIGHT];
-    let mut window = Window::new(
-        "Raytracing - ESC to exit",
-        WIDTH,
-        HEIGHT,
-        WindowOptions {
-            resize: true,
-            ..Default::default()
-        },
-    )
-    .unwrap();
-    window.limit_update_rate(Some(std::time::Duration::from_micros(MICRO_BETWEEN_FRAME)));
-    init_world_and_camera();
-
-    set_noise();
+    // Machines without a display fall back to rendering headless
+    let preview = if cli.headless {
+        None
+    } else {
+        Preview::open(&settings, options.tone_mapping.clone())
+            .map_err(|err| eprintln!("Can't open a window ({err}), rendering headless"))
+            .ok()
+    };
+    let interval = if preview.is_some() {
+        PREVIEW_INTERVAL
+    } else {
+        HEADLESS_INTERVAL
+    };
 
     // Render everything
-    let finished = update_buffer(&mut buffer, &mut window);
+    let renderer = Renderer::new(settings);
+    let mut progress = Progress {
+        preview,
+        interval,
+        last_update: Instant::now(),
+    };
+    // Only removed once the image is saved, the render isn't lost if saving
+    // fails
+    let mut finished_checkpoint = None;
+    let framebuffer = if let Some(path) = &cli.checkpoint {
+        let (framebuffer, complete) =
+            render_with_checkpoints(&renderer, &scene, &scene_id, &cli, path, &mut progress);
+        if complete {
+            finished_checkpoint = Some(path);
+        }
+        Some(framebuffer)
+    } else if cli.interactive && progress.preview.is_some() {
+        Some(render_interactive(&renderer, &mut scene, &mut progress))
+    } else if cli.progressive || cli.interactive {
+        Some(
+            renderer.render_progressive(&scene, |framebuffer, fraction| match framebuffer {
+                Some(framebuffer) => progress.report(framebuffer, fraction, true),
+                None => progress.poll(fraction),
+            }),
+        )
+    } else {
+        renderer.render_with_progress(&scene, |framebuffer, fraction| {
+            progress.report(framebuffer, fraction, false)
+        })
+    };
 
     eprintln!(
         "\rFinished in {:.2}s",
         now.elapsed().as_millis() as f32 / 1000.0
     );
 
-    if finished {
-        save_buffer(&buffer, save_path);
+    let image = framebuffer.map(|framebuffer| framebuffer.to_image());
+    if let Some(image) = &image {
+        let saved = match (&cli.composite, renderer.settings().crop) {
+            (Some(base), Some(crop)) => {
+                image.save_over(base, (crop.x, crop.y), &save_path, format, &options)
+            }
+            _ => image.save_with(&save_path, format, &options),
+        };
+        if let Err(err) = saved {
+            exit_with_error(&format!("can't save {}: {err}", save_path.display()));
+        }
 
-        eprintln!("Image saved to {save_path}");
+        eprintln!("Image saved to {}", save_path.display());
+        if let Some(path) = finished_checkpoint {
+            fs::remove_file(path).ok();
+        }
     }
 
-    // Loop to keep window open
-    while window.is_open() && !window.is_key_down(Key::Escape) {
-        window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+    // Keep window open
+    if let (Some(preview), Some(image)) = (progress.preview, &image) {
+        preview.wait_for_close(image);
     }
 }
 
-fn save_buffer(buffer: &[u32], path: impl AsRef<path::Path>) {
-    let mut buffer2 = RgbImage::new(WIDTH as u32, HEIGHT as u32);
-    buffer2.pixels_mut().enumerate().for_each(|(xy, color)| {
-        let [_, r, g, b] = buffer[xy].to_be_bytes();
-        *color = Rgb([r, g, b]);
-    });
-    buffer2.save(path).unwrap();
+// Shows how far a render is, in the window if there's one
+struct Progress {
+    preview: Option<Preview>,
+    // Time between two reports
+    interval: Duration,
+    last_update: Instant,
 }
 
-fn update_buffer(buffer: &mut [u32], window: &mut Window) -> bool {
-    let (sender, receiver) = mpsc::sync_channel(HEIGHT * WIDTH + 1);
-    thread::spawn(move || {
-        (0..(HEIGHT * WIDTH)).into_par_iter().for_each(|xy| {
-            let (x, y) = space_filler::filling_curve(xy);
-            let color = pixel_processing(x, HEIGHT - y);
-            if sender.send((x + y * WIDTH, color)).is_err() {}
-        });
-        eprintln!("\r100.0% - Finished computing");
-    });
-    let mut now = std::time::Instant::now();
-    for (i, (xy, (r, g, b))) in receiver.iter().enumerate() {
-        buffer[xy] = from_u8_0rgb(r, g, b);
-        if now.elapsed().as_micros() as u64 > MICRO_BETWEEN_FRAME + 100 {
-            now = std::time::Instant::now();
-            window.update_with_buffer(buffer, WIDTH, HEIGHT).unwrap();
-            if window.is_key_down(Key::Escape) {
-                return false;
-            }
-            let progress = i as f32 / (HEIGHT * WIDTH) as f32;
-            eprint!("\r{:.1}%", progress * 100.0);
-            stdout().flush().unwrap();
+impl Progress {
+    // False once the render should stop
+    fn report(&mut self, framebuffer: &Framebuffer, progress: f32, pass_done: bool) -> bool {
+        // The window is refreshed after every pass of a progressive render
+        if !pass_done && self.last_update.elapsed() <= self.interval {
+            return true;
+        }
+        if let Some(preview) = self.preview.as_mut() {
+            preview.update(framebuffer);
         }
+        self.reported(progress)
     }
-    true
-}
 
-fn pixel_processing(i: usize, j: usize) -> (u8, u8, u8) {
-    let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-    for s in 0..SAMPLE_PER_PIXEL {
-        // Render
-
-        let u = (i as f32 + randx(s)) / (WIDTH - 1) as f32;
-        let v = (j as f32 + randy(s)) / (HEIGHT - 1) as f32;
-        let ray = CAMERA.get().unwrap().get_ray(u, v);
-        let r = ray_color(ray, WORLD.get().unwrap());
-        pixel_color.x += r.x.min(1.0);
-        pixel_color.y += r.y.min(1.0);
-        pixel_color.z += r.z.min(1.0);
+    // During a pass of a progressive render the image doesn't change, only
+    // keep the window responsive
+    fn poll(&mut self, progress: f32) -> bool {
+        if self.last_update.elapsed() <= self.interval {
+            return true;
+        }
+        if let Some(preview) = self.preview.as_mut() {
+            preview.poll();
+        }
+        self.reported(progress)
     }
-    out_color(pixel_color)
-}
 
-fn ray_color(ray: Ray, world: &dyn Hittable) -> Color {
-    use tailcall::tailcall;
-    #[tailcall]
-    fn _ray_color(ray: ray::Ray, world: &dyn Hittable, depth: usize, accumulator: Color) -> Color {
-        if depth == 0 {
-            return Color::new(0.0, 0.0, 0.0);
-        }
-        if let Some(rec) = world.hit(&ray, 0.001, f32::INFINITY) {
-            match rec.material.scatter(&ray, &rec) {
-                ScatterResponse::Scatter(attenuation, scattered) => {
-                    return _ray_color(
-                        scattered,
-                        world,
-                        depth - 1,
-                        attenuation.component_mul(&accumulator),
-                    );
-                }
-                ScatterResponse::Absorb(absorbtion) => {
-                    return absorbtion.component_mul(&accumulator);
-                }
-            }
+    fn reported(&mut self, progress: f32) -> bool {
+        self.last_update = Instant::now();
+        if self.preview.as_ref().is_some_and(Preview::should_close) {
+            return false;
         }
-
-        sky_color(&ray, depth).component_mul(&accumulator)
+        eprint!("\r{:.1}%", progress * 100.0);
+        stdout().flush().unwrap();
+        true
     }
-    _ray_color(ray, world, MAX_DEPTH, Color::new(1.0, 1.0, 1.0))
 }
 
-fn out_color(pixel_color: Color) -> (u8, u8, u8) {
-    let scale = 1.0 / SAMPLE_PER_PIXEL as f32;
-    let ir = ((pixel_color.x * scale).sqrt().clamp(0.0, 0.999) * 256.0) as u8;
-    let ig = ((pixel_color.y * scale).sqrt().clamp(0.0, 0.999) * 256.0) as u8;
-    let ib = ((pixel_color.z * scale).sqrt().clamp(0.0, 0.999) * 256.0) as u8;
-    (ir, ig, ib)
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(1);
 }
 
-// Get a different value for small differences in seed by casting its to a u64.
-fn f32_to_unique_u64(seed: f32) -> u64 {
-    unsafe {
-        // Safety: this function always takes an f64, so trasnmuting to an u64 is safe.
-        std::mem::transmute_copy::<_, u64>(&(seed as f64))
+// Progressive render saving a checkpoint every once in a while and when it
+// ends. Returns the render and whether it's complete
+fn render_with_checkpoints(
+    renderer: &Renderer,
+    scene: &Scene,
+    scene_id: &str,
+    cli: &Cli,
+    path: &path::Path,
+    progress: &mut Progress,
+) -> (Framebuffer, bool) {
+    let settings = renderer.settings();
+    let start = if cli.resume {
+        let checkpoint = Checkpoint::load(path, settings, scene_id)
+            .unwrap_or_else(|err| exit_with_error(&format!("can't resume: {err}")));
+        eprintln!("Resuming after {} passes", checkpoint.passes);
+        checkpoint
+    } else {
+        Checkpoint::new(settings)
+    };
+    let save = |checkpoint: &Checkpoint| {
+        if let Err(err) = checkpoint.save(path, settings, scene_id) {
+            eprintln!("\rcan't save the checkpoint: {err}");
+        }
+    };
+
+    let interval = Duration::from_secs(cli.checkpoint_interval as u64);
+    let mut last_save = Instant::now();
+    let mut stopped = false;
+    let checkpoint = renderer.resume_progressive(scene, start, |checkpoint, fraction| {
+        let Some(checkpoint) = checkpoint else {
+            stopped = !progress.poll(fraction);
+            return !stopped;
+        };
+        if last_save.elapsed() > interval {
+            last_save = Instant::now();
+            save(checkpoint);
+        }
+        stopped = !progress.report(&checkpoint.cropped(settings), fraction, true);
+        !stopped
+    });
+    save(&checkpoint);
+    if stopped {
+        eprintln!("\rCheckpoint saved to {}", path.display());
     }
+    (checkpoint.cropped(settings).into_owned(), !stopped)
 }
 
-#[inline]
-fn random_in_unit_sphere(seed: f32) -> glm::Vec3 {
-    let r = &mut StdRng::seed_from_u64(f32_to_unique_u64(seed));
-    glm::make_vec3(&UnitBall.sample(r)).normalize()
+// Progressive render starting over, from one sample per pixel, whenever the
+// camera is moved in the window. Returns the last render once the window is
+// closed
+fn render_interactive(
+    renderer: &Renderer,
+    scene: &mut Scene,
+    progress: &mut Progress,
+) -> Framebuffer {
+    eprintln!("{}", preview::CONTROLS);
+    let mut camera = scene.camera.settings;
+    loop {
+        let mut moved = false;
+        // Inputs are also read between tiles, a move doesn't wait for the
+        // end of the pass
+        let framebuffer = renderer.render_progressive(scene, |framebuffer, fraction| {
+            let go_on = match framebuffer {
+                Some(framebuffer) => progress.report(framebuffer, fraction, true),
+                None => progress.poll(fraction),
+            };
+            let preview = progress
+                .preview
+                .as_mut()
+                .expect("Interactive renders have a window");
+            moved = preview.navigate(&mut camera);
+            go_on && !moved
+        });
+        if !moved {
+            let preview = progress
+                .preview
+                .as_mut()
+                .expect("Interactive renders have a window");
+            if preview.should_close()
+                || !preview.wait_for_move(&framebuffer.to_image(), &mut camera)
+            {
+                return framebuffer;
+            }
+        }
+        scene.camera = Camera::from_settings(camera);
+    }
 }
 
-#[allow(unused)]
-#[inline]
-fn random_in_hemishpere(normal: &glm::Vec3, seed: f32) -> glm::Vec3 {
-    let in_unit_sphere = random_in_unit_sphere(seed);
-    if in_unit_sphere.dot(normal) > 0.0 {
-        in_unit_sphere
-    } else {
-        -in_unit_sphere
-    }
+// Build the scene of a scene file. Returns the render settings, the scene and
+// the output path of the scene
+fn load_scene_file(path: &path::Path, cli: &Cli) -> (RenderSettings, Scene, path::PathBuf, String) {
+    let scene = scene_file::parse_scene(path).and_then(|mut description| {
+        cli.apply(&mut description.settings);
+        let settings = description.settings.clone();
+        let output = description.output.clone();
+        let (scene, digest) = description.build_with_digest()?;
+        Ok((settings, scene, output, format!("file {digest:016x}")))
+    });
+    let (settings, scene, output, scene_id) = match scene {
+        Ok(scene) => scene,
+        Err(err) => exit_with_error(&format!("invalid scene file {err}")),
+    };
+    let output = output.unwrap_or_else(|| "scene_file_output.png".into());
+    (settings, scene, output.into(), scene_id)
 }
@@ -1,11 +1,9 @@
 use std::sync::Arc;
 
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
-
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
-use crate::{f32_to_unique_u64, random_in_unit_sphere, Color};
+use crate::sampler::{random_unit_vector, Sampler};
+use crate::Color;
 
 pub enum ScatterResponse {
     Scatter(Color, Ray),
@@ -14,8 +12,9 @@ pub enum ScatterResponse {
 use ScatterResponse::*;
 
 pub trait Material {
-    // Output: Option<(attenuation, scattered_ray)>
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> ScatterResponse;
+    // Output: Option<(attenuation, scattered_ray)>. The random numbers come
+    // from `sampler`
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResponse;
 }
 
 pub type MaterialObject = Arc<dyn Material + Send + Sync>;
@@ -32,8 +31,13 @@ impl Lambertian {
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _ray_in: &Ray, rec: &HitRecord) -> ScatterResponse {
-        let mut scaterred_direction = rec.normal + random_in_unit_sphere(rec.point.sum());
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> ScatterResponse {
+        let mut scaterred_direction = rec.normal + random_unit_vector(sampler);
         if scaterred_direction.is_empty() {
             scaterred_direction = rec.normal;
         }
@@ -55,11 +59,11 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> ScatterResponse {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResponse {
         let reflected = glm::reflect_vec(&ray_in.dir.normalize(), &rec.normal);
         let scattered = Ray::new(
             rec.point,
-            reflected + self.fuzz * random_in_unit_sphere(rec.point.sum()),
+            reflected + self.fuzz * random_unit_vector(sampler),
         );
         if scattered.dir.dot(&rec.normal) > 0.0 {
             Scatter(self.albedo, scattered)
@@ -81,7 +85,12 @@ impl LightSource {
 }
 
 impl Material for LightSource {
-    fn scatter(&self, _ray_in: &Ray, _rec: &HitRecord) -> ScatterResponse {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut dyn Sampler,
+    ) -> ScatterResponse {
         Absorb(self.albedo)
     }
 }
@@ -98,7 +107,7 @@ impl Dielectic {
 }
 
 impl Material for Dielectic {
-    fn scatter(&self, ray_in: &Ray, rec: &HitRecord) -> ScatterResponse {
+    fn scatter(&self, ray_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> ScatterResponse {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let refraction_ratio = if rec.front_face {
             1.0 / self.refraction_i
@@ -112,8 +121,7 @@ impl Material for Dielectic {
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
 
-        let can_reflect = reflectance(cos_theta, refraction_ratio)
-            > StdRng::seed_from_u64(f32_to_unique_u64(rec.point.sum())).gen();
+        let can_reflect = reflectance(cos_theta, refraction_ratio) > sampler.next_1d();
         let direction = if cannot_refract || can_reflect {
             glm::reflect_vec(&unit_direction, &rec.normal)
         } else {
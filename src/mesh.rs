@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::hittable::*;
+use crate::hittable_list::HittableList;
+use crate::material::MaterialObject;
+use crate::ray::Ray;
+use crate::triangle::{intersect, triangle_box};
+
+// A corner of a triangle. Like in OBJ files, positions, normals and texture
+// coordinates are indexed separately so they can be shared between faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub normal: Option<usize>,
+    pub uv: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct MeshData {
+    pub positions: Vec<glm::Vec3>,
+    pub normals: Vec<glm::Vec3>,
+    pub uvs: Vec<glm::Vec2>,
+    pub triangles: Vec<[MeshVertex; 3]>,
+}
+
+impl MeshData {
+    fn vertices(&self, triangle: usize) -> [glm::Vec3; 3] {
+        self.triangles[triangle].map(|v| self.positions[v.position])
+    }
+}
+
+// An indexed triangle mesh. Triangles are stored in their own BVH so the mesh
+// can be added to the world as a single object
+pub struct TriangleMesh {
+    pub data: Arc<MeshData>,
+    bvh: Bvh,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData, material: MaterialObject) -> Box<Self> {
+        let data = Arc::new(data);
+        let mut triangles = HittableList::default();
+        for index in 0..data.triangles.len() {
+            triangles.add(Box::new(MeshTriangle {
+                mesh: data.clone(),
+                index,
+                material: material.clone(),
+            }));
+        }
+        Box::new(Self {
+            data,
+            bvh: Bvh::new(triangles),
+        })
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        self.bvh.hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+    material: MaterialObject,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [a, b, c] = self.mesh.vertices(self.index);
+        let (t, b1, b2) = intersect(ray, &a, &b, &c, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let corners = &self.mesh.triangles[self.index];
+
+        let uv = match corners.map(|v| v.uv) {
+            [Some(uv0), Some(uv1), Some(uv2)] => {
+                let uvs = &self.mesh.uvs;
+                b0 * uvs[uv0] + b1 * uvs[uv1] + b2 * uvs[uv2]
+            }
+            _ => glm::vec2(b1, b2),
+        };
+
+        let outward_normal = (b - a).cross(&(c - a)).normalize();
+        let mut rec = HitRecord::new_with_front_face(
+            ray.at(t),
+            t,
+            uv,
+            self.material.clone(),
+            ray,
+            &outward_normal,
+        );
+        // Smooth shading, interpolate the normals given for each vertex
+        if let [Some(n0), Some(n1), Some(n2)] = corners.map(|v| v.normal) {
+            let normals = &self.mesh.normals;
+            let shading_normal = b0 * normals[n0] + b1 * normals[n1] + b2 * normals[n2];
+            rec.set_shading_normal(&shading_normal);
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_box(&self.mesh.vertices(self.index)))
+    }
+}
@@ -1,17 +1,24 @@
 #![allow(unused)]
 
 use crate::material::*;
+use crate::settings::RenderSettings;
+use crate::sky::Sky;
 use crate::sphere::*;
 use crate::*;
 
 // Give a name to the output file. Png is the recommended file format
 pub const OUTPUT_NAME: &str = "my_scene_output.png";
 
-pub const ASPECT_RATIO: f32 = 16.0 / 9.0;
-pub const WIDTH: usize = 900;
-pub const HEIGHT: usize = (WIDTH as f32 / ASPECT_RATIO) as usize;
-pub const SAMPLE_PER_PIXEL: usize = 200;
-pub const MAX_DEPTH: usize = 25;
+pub fn render_settings() -> RenderSettings {
+    RenderSettings {
+        width: 900,
+        height: 506,
+        samples_per_pixel: 200,
+        max_depth: 25,
+        seed: SEED,
+        ..Default::default()
+    }
+}
 
 const MAX: f32 = 0.95;
 const DIF: f32 = 0.2;
@@ -27,8 +34,8 @@ const LMED: f32 = 0.60;
 const LDIF: f32 = 0.60;
 pub const LIGHT: LightSource = LightSource::new(Color::new(LMAX, LMED, LDIF));
 
-pub fn init_world_and_camera() {
-    let mut r = StdRng::seed_from_u64(SEED);
+pub fn build_scene(settings: &RenderSettings) -> Scene {
+    let mut r = StdRng::seed_from_u64(settings.seed);
 
     let look_from = glm::vec3(0.0, 0.0, 0.0);
     let look_at = glm::vec3(0.0, 0.0, -2.5);
@@ -40,15 +47,11 @@ pub fn init_world_and_camera() {
         look_at,
         v_up,
         90.0,
-        ASPECT_RATIO,
+        settings.aspect_ratio(),
         aperture,
         dist_to_focus,
     );
 
-    if CAMERA.set(camera).is_err() {
-        panic!("Tried to set CAMERA twice. This is a bug");
-    }
-
     let mut world = HittableList::default();
     world.add(Sphere::new(
         glm::vec3(0.0, -100.9, -1.0),
@@ -101,23 +104,11 @@ pub fn init_world_and_camera() {
         Arc::new(LIGHT),
     ));
     world.add(Sphere::new(glm::vec3(0.0, 1.0, -8.0), 1.5, Arc::new(LIGHT)));
-    if WORLD.set(world).is_err() {
-        panic!("Tried to set WORLD twice. This is a bug");
-    }
+    Scene::new(world, camera, SKY_COLOR)
 }
 
-pub fn sky_color(ray: &Ray, depth: usize) -> Color {
-    let unit_dir = ray.dir.normalize();
-    let t = 0.5 * (unit_dir.y + 1.0);
-    // let d = MAX_DEPTH as f32 - MAX_DEPTH as f32;
-    // let p = ((unit_dir.x) * 5.0) as i32;
-    let s = 0.05;
-    //     if p % 3 == 0 {
-    //     d
-    // } else if p % 3 == 1 {
-    //     d / 2.0
-    // } else {
-    //     0.0
-    // };
-    Color::new(0.0, 0.00, 0.00).lerp(&Color::new(s, s / 2.0, s / 3.0), t)
-}
+const SKY_GLOW: f32 = 0.05;
+pub const SKY_COLOR: Sky = Sky::Gradient {
+    bottom: Color::new(0.0, 0.00, 0.00),
+    top: Color::new(SKY_GLOW, SKY_GLOW / 2.0, SKY_GLOW / 3.0),
+};
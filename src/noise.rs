@@ -1,51 +1,117 @@
 #![allow(unused)]
 
+use crate::settings::RenderSettings;
 use crate::*;
 
-pub static NOISE: Lazy<[(f32, f32); SAMPLE_PER_PIXEL]> = Lazy::new(|| {
-    blue_noise(SAMPLE_PER_PIXEL)
-        .iter()
-        .map(|p| (p.x, p.y))
-        .collect::<Vec<(f32, f32)>>()
-        .try_into()
-        .unwrap()
-});
-
-#[inline]
-pub fn randx(i: usize) -> f32 {
-    NOISE.get(i).unwrap().0 //.get(i).unwrap().0
-                            // rand::random()
+// One pixel offset per sample, shared by every pixel. Samplers shift it by a
+// different amount in each pixel
+pub struct Noise {
+    samples: Vec<(f32, f32)>,
 }
 
-#[inline]
-pub fn randy(i: usize) -> f32 {
-    NOISE.get(i).unwrap().1 //.get(i).unwrap().1
-                            // rand::random()
+impl Noise {
+    pub fn new(settings: &RenderSettings) -> Self {
+        let samples = blue_noise(settings.max_samples_per_pixel(), settings.seed)
+            .iter()
+            .map(|p| (p.x, p.y))
+            .collect();
+        Self { samples }
+    }
+
+    #[inline]
+    pub fn randx(&self, i: usize) -> f32 {
+        self.samples.get(i).unwrap().0
+    }
+
+    #[inline]
+    pub fn randy(&self, i: usize) -> f32 {
+        self.samples.get(i).unwrap().1
+    }
 }
 
-pub fn blue_noise(amount: usize) -> Vec<glm::Vec2> {
-    let mut r = StdRng::seed_from_u64(SEED);
-    let mut samples = Vec::new();
+// Best-candidate blue noise: each sample is the candidate farthest from the
+// samples before it, so any prefix of the samples is well spread too. The
+// number of candidates is capped and the nearest sample is looked up in a
+// grid, making it linear in the number of samples
+pub fn blue_noise(amount: usize, seed: u64) -> Vec<glm::Vec2> {
+    const MAX_CANDIDATES: usize = 32;
+
+    let mut r = StdRng::seed_from_u64(seed);
+    let mut grid = Grid::new(amount);
+    let mut samples = Vec::with_capacity(amount);
     for sample_index in 0..amount {
         let mut best_score = f32::NEG_INFINITY;
-        let mut best_candidate: Option<glm::Vec2> = None;
-        for _ in 0..sample_index + 1 {
-            let mut score = f32::INFINITY;
+        let mut best_candidate = glm::Vec2::new(0.0, 0.5);
+        for _ in 0..(sample_index + 1).min(MAX_CANDIDATES) {
             let candidate = glm::Vec2::new(r.gen(), r.gen());
-            for test_sample in &samples {
-                let test_score = toroidal_distance(&candidate, test_sample);
-                score = score.min(test_score);
-            }
+            let score = grid.nearest_distance(&candidate, &samples);
             if score > best_score {
                 best_score = score;
-                best_candidate = Some(candidate);
+                best_candidate = candidate;
             }
         }
-        samples.push(best_candidate.unwrap_or(glm::Vec2::new(0.0, 0.5)));
+        grid.insert(&best_candidate, samples.len());
+        samples.push(best_candidate);
     }
     samples
 }
 
+// Samples of the unit square sorted in cells, wrapping around its edges
+struct Grid {
+    size: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    // About one sample per cell once they're all in
+    fn new(amount: usize) -> Self {
+        let size = ((amount as f32).sqrt().ceil() as usize).max(1);
+        Self {
+            size,
+            cells: vec![Vec::new(); size * size],
+        }
+    }
+
+    fn cell_of(&self, point: &glm::Vec2) -> (usize, usize) {
+        let cell = |c: f32| ((c * self.size as f32) as usize).min(self.size - 1);
+        (cell(point.x), cell(point.y))
+    }
+
+    fn insert(&mut self, point: &glm::Vec2, index: usize) {
+        let (x, y) = self.cell_of(point);
+        self.cells[x + y * self.size].push(index);
+    }
+
+    // Toroidal distance to the nearest sample, infinite if there are none.
+    // Looks at rings of cells further and further away until the next ring
+    // can't hold anything closer
+    fn nearest_distance(&self, point: &glm::Vec2, samples: &[glm::Vec2]) -> f32 {
+        let (cx, cy) = self.cell_of(point);
+        let cell_size = 1.0 / self.size as f32;
+        let size = self.size as isize;
+        let mut nearest = f32::INFINITY;
+        for ring in 0..=size / 2 {
+            // Anything in this ring is at least that far away
+            if (ring - 1).max(0) as f32 * cell_size > nearest {
+                break;
+            }
+            for dy in -ring..=ring {
+                for dx in -ring..=ring {
+                    if dx.abs() != ring && dy.abs() != ring {
+                        continue;
+                    }
+                    let x = (cx as isize + dx).rem_euclid(size) as usize;
+                    let y = (cy as isize + dy).rem_euclid(size) as usize;
+                    for &index in &self.cells[x + y * self.size] {
+                        nearest = nearest.min(toroidal_distance(point, &samples[index]));
+                    }
+                }
+            }
+        }
+        nearest
+    }
+}
+
 pub fn toroidal_distance(a: &glm::Vec2, b: &glm::Vec2) -> f32 {
     let mut dx = (b.x - a.x).abs();
     let mut dy = (b.y - a.y).abs();
@@ -58,12 +124,3 @@ pub fn toroidal_distance(a: &glm::Vec2, b: &glm::Vec2) -> f32 {
     }
     (dx * dx + dy * dy).sqrt()
 }
-pub fn set_noise() {
-    // let noise = blue_noise()
-    //     .iter()
-    //     .map(|p| (p.x, p.y))
-    //     .collect::<Vec<(f32, f32)>>();
-    // NOISE
-    //     .set(noise.try_into().unwrap())
-    //     .expect("NOISE set twice. This is a bug");
-}
@@ -0,0 +1,469 @@
+// Loader for Wavefront OBJ models and their MTL material libraries. Only the
+// geometry statements (v, vt, vn, f) and the material statements that map onto
+// our materials are supported, the rest is ignored.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, fs};
+
+use crate::hittable_list::HittableList;
+use crate::material::*;
+use crate::mesh::{MeshData, MeshVertex, TriangleMesh};
+use crate::Color;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {err}", path.display()),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{line}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// The part of a model using a single material
+pub struct ObjMesh {
+    pub material_name: Option<String>,
+    pub data: MeshData,
+    pub material: MaterialObject,
+}
+
+impl ObjMesh {
+    pub fn into_hittable(self) -> Box<TriangleMesh> {
+        TriangleMesh::new(self.data, self.material)
+    }
+}
+
+// Material used by faces that don't reference any MTL material
+pub const DEFAULT_MATERIAL: Lambertian = Lambertian::new(Color::new(0.8, 0.8, 0.8));
+
+// Load every mesh of an OBJ file and add them to the world
+pub fn add_obj(world: &mut HittableList, path: impl AsRef<Path>) -> Result<(), ObjError> {
+    for mesh in load_obj(path)? {
+        world.add(mesh.into_hittable());
+    }
+    Ok(())
+}
+
+// Load an OBJ file, splitting it in one mesh per material
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Vec<ObjMesh>, ObjError> {
+    load_obj_with(path, &mut |_| {})
+}
+
+// Same as `load_obj`, giving the contents of the OBJ file and of its MTL
+// libraries to `on_file` as they're read
+pub fn load_obj_with(
+    path: impl AsRef<Path>,
+    on_file: &mut dyn FnMut(&str),
+) -> Result<Vec<ObjMesh>, ObjError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    on_file(&source);
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials = HashMap::new();
+    // Faces grouped by material, in order of first use
+    let mut groups: Vec<(Option<String>, Vec<[MeshVertex; 3]>)> = vec![(None, Vec::new())];
+    let mut current_group = 0;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_owned(),
+            line: line_index + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        match keyword {
+            "v" => positions.push(parse_vec3(&args).map_err(error)?),
+            "vn" => normals.push(parse_vec3(&args).map_err(error)?),
+            "vt" => {
+                let uv = parse_floats(&args, 1).map_err(error)?;
+                uvs.push(glm::vec2(uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!(
+                        "a face needs at least 3 vertices, got {}",
+                        args.len()
+                    )));
+                }
+                let counts = (positions.len(), uvs.len(), normals.len());
+                let vertices = args
+                    .iter()
+                    .map(|arg| parse_face_vertex(arg, counts))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                // Triangulate polygons as a fan
+                for i in 1..vertices.len() - 1 {
+                    groups[current_group]
+                        .1
+                        .push([vertices[0], vertices[i], vertices[i + 1]]);
+                }
+            }
+            "mtllib" => {
+                for file in args {
+                    let mtl_path = path.parent().unwrap_or(Path::new("")).join(file);
+                    // Its materials are unknown, the faces using them get the
+                    // default one
+                    match fs::read_to_string(&mtl_path) {
+                        Ok(mtl_source) => {
+                            on_file(&mtl_source);
+                            materials.extend(parse_mtl(&mtl_path, &mtl_source)?);
+                        }
+                        Err(err) => eprintln!(
+                            "warning: {}:{}: {}: {err}, using the default material",
+                            path.display(),
+                            line_index + 1,
+                            mtl_path.display()
+                        ),
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                current_group = match groups.iter().position(|(n, _)| n.as_ref() == Some(&name)) {
+                    Some(index) => index,
+                    None => {
+                        // Exporters often reference materials of a missing
+                        // or partial library, the model is still usable
+                        if !materials.contains_key(&name) {
+                            eprintln!(
+                                "warning: {}:{}: unknown material `{name}`, using the default one",
+                                path.display(),
+                                line_index + 1
+                            );
+                        }
+                        groups.push((Some(name), Vec::new()));
+                        groups.len() - 1
+                    }
+                };
+            }
+            // Objects, groups, smoothing groups, lines, points...
+            _ => {}
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|(_, triangles)| !triangles.is_empty())
+        .map(|(material_name, triangles)| {
+            let material = material_name
+                .as_ref()
+                .and_then(|name| materials.get(name).cloned())
+                .unwrap_or_else(|| Arc::new(DEFAULT_MATERIAL));
+            ObjMesh {
+                data: compact_mesh(&positions, &normals, &uvs, &triangles),
+                material_name,
+                material,
+            }
+        })
+        .collect())
+}
+
+// Build a mesh containing only the vertices used by `triangles`
+fn compact_mesh(
+    positions: &[glm::Vec3],
+    normals: &[glm::Vec3],
+    uvs: &[glm::Vec2],
+    triangles: &[[MeshVertex; 3]],
+) -> MeshData {
+    fn remap<T: Copy>(
+        index: usize,
+        map: &mut HashMap<usize, usize>,
+        from: &[T],
+        to: &mut Vec<T>,
+    ) -> usize {
+        *map.entry(index).or_insert_with(|| {
+            to.push(from[index]);
+            to.len() - 1
+        })
+    }
+    let mut mesh = MeshData::default();
+    let (mut position_map, mut normal_map, mut uv_map) =
+        (HashMap::new(), HashMap::new(), HashMap::new());
+    for triangle in triangles {
+        mesh.triangles.push(triangle.map(|v| {
+            MeshVertex {
+                position: remap(
+                    v.position,
+                    &mut position_map,
+                    positions,
+                    &mut mesh.positions,
+                ),
+                normal: v
+                    .normal
+                    .map(|n| remap(n, &mut normal_map, normals, &mut mesh.normals)),
+                uv: v.uv.map(|uv| remap(uv, &mut uv_map, uvs, &mut mesh.uvs)),
+            }
+        }));
+    }
+    mesh
+}
+
+// Parse a `v`, `v/vt`, `v//vn` or `v/vt/vn` face vertex. Indices start at 1
+// and negative ones are relative to the end of the lists
+fn parse_face_vertex(
+    arg: &str,
+    (position_count, uv_count, normal_count): (usize, usize, usize),
+) -> Result<MeshVertex, String> {
+    let index = |s: Option<&str>, count: usize, kind: &str| -> Result<Option<usize>, String> {
+        let s = match s {
+            Some(s) if !s.is_empty() => s,
+            _ => return Ok(None),
+        };
+        let i: isize = s
+            .parse()
+            .map_err(|_| format!("invalid {kind} index `{s}`"))?;
+        let resolved = if i < 0 { count as isize + i } else { i - 1 };
+        if i == 0 || resolved < 0 || resolved >= count as isize {
+            return Err(format!("{kind} index {i} is out of range"));
+        }
+        Ok(Some(resolved as usize))
+    };
+    let mut parts = arg.split('/');
+    let position = index(parts.next(), position_count, "vertex")?
+        .ok_or_else(|| format!("missing vertex index in `{arg}`"))?;
+    let uv = index(parts.next(), uv_count, "texture coordinate")?;
+    let normal = index(parts.next(), normal_count, "normal")?;
+    Ok(MeshVertex {
+        position,
+        normal,
+        uv,
+    })
+}
+
+fn parse_floats(args: &[&str], min: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min {
+        return Err(format!("expected {min} numbers, got {}", args.len()));
+    }
+    args.iter()
+        .map(|s| s.parse().map_err(|_| format!("invalid number `{s}`")))
+        .collect()
+}
+
+fn parse_vec3(args: &[&str]) -> Result<glm::Vec3, String> {
+    let v = parse_floats(args, 3)?;
+    Ok(glm::vec3(v[0], v[1], v[2]))
+}
+
+// Properties of a MTL material that we know how to map onto ours
+struct MtlMaterial {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f32,
+    refraction_i: f32,
+    dissolve: f32,
+    illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::zeros(),
+            emission: Color::zeros(),
+            shininess: 0.0,
+            refraction_i: 1.5,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlMaterial {
+    // Emissive materials become lights, transparent ones glass, mirror-like
+    // ones metal and everything else is diffuse
+    fn to_material(&self) -> MaterialObject {
+        if self.emission.max() > 0.0 {
+            Arc::new(LightSource::new(self.emission))
+        } else if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            Arc::new(Dielectic::new(self.refraction_i))
+        } else if matches!(self.illum, 3 | 5 | 8) {
+            // Convert the Phong exponent to a roughness
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().min(1.0);
+            Arc::new(Metal::new(self.specular, fuzz))
+        } else {
+            Arc::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+pub fn load_mtl(path: &Path) -> Result<HashMap<String, MaterialObject>, ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_owned(), e))?;
+    parse_mtl(path, &source)
+}
+
+// `path` is only used in errors
+fn parse_mtl(path: &Path, source: &str) -> Result<HashMap<String, MaterialObject>, ObjError> {
+    let mut parsed: Vec<(String, MtlMaterial)> = Vec::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_owned(),
+            line: line_index + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or_default();
+        let mut tokens = line.split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+        if keyword == "newmtl" {
+            parsed.push((args.join(" "), MtlMaterial::default()));
+            continue;
+        }
+        let Some((_, material)) = parsed.last_mut() else {
+            return Err(error(format!("`{keyword}` before any `newmtl`")));
+        };
+        let float = |args: &[&str]| parse_floats(args, 1).map(|v| v[0]);
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&args).map_err(error)?,
+            "Ks" => material.specular = parse_vec3(&args).map_err(error)?,
+            "Ke" => material.emission = parse_vec3(&args).map_err(error)?,
+            "Ns" => material.shininess = float(&args).map_err(error)?,
+            "Ni" => material.refraction_i = float(&args).map_err(error)?,
+            "d" => material.dissolve = float(&args).map_err(error)?,
+            "Tr" => material.dissolve = 1.0 - float(&args).map_err(error)?,
+            "illum" => {
+                material.illum = args
+                    .first()
+                    .and_then(|s| s.parse().ok())
+                    .ok_or_else(|| error("invalid illumination model".to_string()))?
+            }
+            _ => {}
+        }
+    }
+
+    Ok(parsed
+        .into_iter()
+        .map(|(name, material)| (name, material.to_material()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ray_tracing_{}_{name}", std::process::id()))
+    }
+
+    // Load an OBJ file with the given contents
+    fn load(name: &str, source: &str) -> Vec<ObjMesh> {
+        let path = temp_path(name);
+        fs::write(&path, source).unwrap();
+        let meshes = load_obj(&path);
+        fs::remove_file(&path).unwrap();
+        meshes.unwrap()
+    }
+
+    fn vertex(position: usize, uv: Option<usize>, normal: Option<usize>) -> MeshVertex {
+        MeshVertex {
+            position,
+            normal,
+            uv,
+        }
+    }
+
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn face_vertex_formats() {
+        let source = format!(
+            "{QUAD}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf \
+             1/1/1 2/2/1 3/3/1\n"
+        );
+        let meshes = load("formats.obj", &source);
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0].data;
+        assert_eq!(mesh.positions.len(), 3);
+        assert_eq!((mesh.uvs.len(), mesh.normals.len()), (3, 1));
+        let [plain, textured, with_normal, full] = mesh.triangles[..] else {
+            panic!("expected 4 triangles, got {}", mesh.triangles.len());
+        };
+        assert_eq!(plain, [0, 1, 2].map(|i| vertex(i, None, None)));
+        assert_eq!(textured, [0, 1, 2].map(|i| vertex(i, Some(i), None)));
+        assert_eq!(with_normal, [0, 1, 2].map(|i| vertex(i, None, Some(0))));
+        assert_eq!(full, [0, 1, 2].map(|i| vertex(i, Some(i), Some(0))));
+    }
+
+    #[test]
+    fn negative_indices() {
+        let source = format!("{QUAD}vn 0 0 1\nf -3//-1 -2//-1 -1//-1\nf 2 3 4\n");
+        let mesh = &load("negative.obj", &source)[0].data;
+        // Vertices are renumbered in order of use
+        assert_eq!(
+            mesh.triangles[0],
+            [0, 1, 2].map(|i| vertex(i, None, Some(0)))
+        );
+        assert_eq!(mesh.triangles[1], [0, 1, 2].map(|i| vertex(i, None, None)));
+        assert_eq!(mesh.positions[0], glm::vec3(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn polygons_are_fans() {
+        let source = format!("{QUAD}v 0.5 2 0\nf 1 2 3 5 4\n");
+        let mesh = &load("fan.obj", &source)[0].data;
+        let triangles: Vec<[usize; 3]> = mesh
+            .triangles
+            .iter()
+            .map(|triangle| triangle.map(|v| v.position))
+            .collect();
+        assert_eq!(triangles, [[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+        assert_eq!(mesh.positions[3], glm::vec3(0.5, 2.0, 0.0));
+    }
+
+    #[test]
+    fn one_mesh_per_material() {
+        let mtl_path = temp_path("materials.mtl");
+        fs::write(&mtl_path, "newmtl red\nKd 1 0 0\n").unwrap();
+        let mtl_name = mtl_path.file_name().unwrap().to_str().unwrap();
+        let source = format!(
+            "mtllib {mtl_name}\n{QUAD}f 1 2 3\nusemtl red\nf 1 3 4\nusemtl missing\nf 2 3 \
+             4\nusemtl red\nf 1 2 4\n"
+        );
+        let meshes = load("materials.obj", &source);
+        fs::remove_file(&mtl_path).unwrap();
+
+        let summary: Vec<(Option<&str>, usize)> = meshes
+            .iter()
+            .map(|mesh| (mesh.material_name.as_deref(), mesh.data.triangles.len()))
+            .collect();
+        // Unknown materials fall back to the default one instead of failing
+        assert_eq!(summary, [(None, 1), (Some("red"), 2), (Some("missing"), 1)]);
+    }
+
+    #[test]
+    fn missing_material_library() {
+        let source = format!("mtllib missing.mtl\n{QUAD}usemtl red\nf 1 2 3\n");
+        let meshes = load("missing_library.obj", &source);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].material_name.as_deref(), Some("red"));
+        assert_eq!(meshes[0].data.triangles.len(), 1);
+    }
+}
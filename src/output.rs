@@ -0,0 +1,595 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::{fmt, fs};
+
+use image::codecs::hdr::{HdrDecoder, HdrEncoder};
+use image::{ImageFormat, Rgb, RgbImage};
+
+use crate::color::ColorSpace;
+use crate::tonemap::ToneMapping;
+use crate::Color;
+
+// A rendered image of linear radiance, stored row by row from the top left
+// corner. Values aren't clamped, they can go above 1
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    // Per pixel data besides the color, only written by formats that can
+    // store arbitrary channels
+    pub extra_channels: Vec<ExtraChannel>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExtraChannel {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    Tga,
+    Tiff,
+    // Linear radiance as 32 bits floats, or 16 bits with `half_float`
+    Exr { half_float: bool },
+    // Radiance RGBE, 8 bits per channel with a shared exponent
+    Hdr,
+    // Portable float map, 32 bits floats
+    Pfm,
+}
+
+impl OutputFormat {
+    // Guess the format from the extension of a path
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "tga" => Some(OutputFormat::Tga),
+            "tif" | "tiff" => Some(OutputFormat::Tiff),
+            "exr" => Some(OutputFormat::Exr { half_float: false }),
+            "hdr" => Some(OutputFormat::Hdr),
+            "pfm" => Some(OutputFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    // True for formats keeping the linear, unclamped radiance
+    pub fn is_hdr(&self) -> bool {
+        matches!(
+            self,
+            OutputFormat::Exr { .. } | OutputFormat::Hdr | OutputFormat::Pfm
+        )
+    }
+}
+
+// How the radiance of an image is turned into the values of a file
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    // Only for 8 bits formats, the others keep the radiance as it is
+    pub tone_mapping: ToneMapping,
+    pub color_space: ColorSpace,
+    // Store linear values in 8 bits formats instead of applying the transfer
+    // function of the color space
+    pub linear: bool,
+}
+
+impl OutputOptions {
+    // Encode a linear Rec.709 radiance to 8 bits per channel
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3] {
+        let color = self
+            .color_space
+            .from_rec709(self.tone_mapping.apply(color))
+            .map(|c| c.clamp(0.0, 1.0));
+        let encode = |c: f32| {
+            let c = if self.linear {
+                c
+            } else {
+                self.color_space.encode(c)
+            };
+            (c * 255.0).round() as u8
+        };
+        [encode(color.x), encode(color.y), encode(color.z)]
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Exr(exr::error::Error),
+    // The image saved over can't be loaded
+    Base(LoadError),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => err.fmt(f),
+            SaveError::Image(err) => err.fmt(f),
+            SaveError::Exr(err) => err.fmt(f),
+            SaveError::Base(err) => write!(f, "can't load the base image: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Image(image::ImageError),
+    Exr(exr::error::Error),
+    // The file isn't an image of the format it's loaded as
+    Invalid(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => err.fmt(f),
+            LoadError::Image(err) => err.fmt(f),
+            LoadError::Exr(err) => err.fmt(f),
+            LoadError::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::zeros(); width * height],
+            extra_channels: Vec::new(),
+        }
+    }
+
+    // 8 bits per channel version of the image, ready to be displayed
+    pub fn to_rgb8(&self, options: &OutputOptions) -> Vec<[u8; 3]> {
+        self.pixels
+            .iter()
+            .map(|pixel| options.to_rgb8(*pixel))
+            .collect()
+    }
+
+    // Save as an sRGB image, clipping the radiance above 1
+    pub fn save(&self, path: impl AsRef<Path>, format: OutputFormat) -> Result<(), SaveError> {
+        self.save_with(path, format, &OutputOptions::default())
+    }
+
+    pub fn save_with(
+        &self,
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+        options: &OutputOptions,
+    ) -> Result<(), SaveError> {
+        if format.is_hdr() {
+            return self.in_color_space(options.color_space).save_hdr_format(
+                path,
+                format,
+                options.color_space,
+            );
+        }
+        let mut buffer = RgbImage::new(self.width as u32, self.height as u32);
+        buffer
+            .pixels_mut()
+            .zip(self.to_rgb8(options))
+            .for_each(|(color, pixel)| *color = Rgb(pixel));
+        buffer
+            .save_with_format(path, ldr_format(format))
+            .map_err(SaveError::Image)
+    }
+
+    // Save the image pasted into the image file `base` with its top left
+    // corner at `(x, y)`, to update part of a render. `base` has to be in the
+    // same format and saved with the same options, it can be `path` itself
+    pub fn save_over(
+        &self,
+        base: impl AsRef<Path>,
+        (x, y): (usize, usize),
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+        options: &OutputOptions,
+    ) -> Result<(), SaveError> {
+        let fits = |width: usize, height: usize| {
+            if x + self.width <= width && y + self.height <= height {
+                Ok(())
+            } else {
+                Err(SaveError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the image doesn't fit in the {width}x{height} base image"),
+                )))
+            }
+        };
+        if format.is_hdr() {
+            let mut full = Image::load(base, format).map_err(SaveError::Base)?;
+            fits(full.width, full.height)?;
+            // The base is already in the output color space
+            full.paste(&self.in_color_space(options.color_space), (x, y));
+            return full.save_hdr_format(path, format, options.color_space);
+        }
+        let mut full = image::open(base)
+            .map_err(|err| SaveError::Base(LoadError::Image(err)))?
+            .to_rgb8();
+        fits(full.width() as usize, full.height() as usize)?;
+        for (i, pixel) in self.to_rgb8(options).into_iter().enumerate() {
+            let (pixel_x, pixel_y) = (x + i % self.width, y + i / self.width);
+            full.put_pixel(pixel_x as u32, pixel_y as u32, Rgb(pixel));
+        }
+        full.save_with_format(path, ldr_format(format))
+            .map_err(SaveError::Image)
+    }
+
+    // Copy `other` over this image with its top left corner at `(x, y)`,
+    // along with the extra channels both images have
+    pub fn paste(&mut self, other: &Image, (x, y): (usize, usize)) {
+        let width = other.width.min(self.width.saturating_sub(x));
+        for other_y in 0..other.height.min(self.height.saturating_sub(y)) {
+            let (start, other_start) = (x + (y + other_y) * self.width, other_y * other.width);
+            self.pixels[start..start + width]
+                .copy_from_slice(&other.pixels[other_start..other_start + width]);
+            for channel in &mut self.extra_channels {
+                let Some(other_channel) = other
+                    .extra_channels
+                    .iter()
+                    .find(|other_channel| other_channel.name == channel.name)
+                else {
+                    continue;
+                };
+                channel.values[start..start + width]
+                    .copy_from_slice(&other_channel.values[other_start..other_start + width]);
+            }
+        }
+    }
+
+    // Load an image saved in one of the HDR formats. Colors are read as they
+    // are, in the color space they were saved in
+    pub fn load(path: impl AsRef<Path>, format: OutputFormat) -> Result<Image, LoadError> {
+        match format {
+            OutputFormat::Exr { .. } => Image::load_exr(path),
+            OutputFormat::Hdr => Image::load_hdr(path),
+            OutputFormat::Pfm => Image::load_pfm(path),
+            _ => Err(LoadError::Invalid(
+                "only HDR images can be loaded".to_string(),
+            )),
+        }
+    }
+
+    // Width and height of an image file, without loading its pixels
+    pub fn dimensions(
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+    ) -> Result<(usize, usize), LoadError> {
+        let path = path.as_ref();
+        match format {
+            OutputFormat::Exr { .. } => {
+                let meta =
+                    exr::meta::MetaData::read_from_file(path, false).map_err(LoadError::Exr)?;
+                let header = meta
+                    .headers
+                    .first()
+                    .ok_or_else(|| LoadError::Invalid("EXR image without layers".to_string()))?;
+                Ok((header.layer_size.width(), header.layer_size.height()))
+            }
+            OutputFormat::Hdr => {
+                let file = BufReader::new(File::open(path).map_err(LoadError::Io)?);
+                let metadata = HdrDecoder::new(file).map_err(LoadError::Image)?.metadata();
+                Ok((metadata.width as usize, metadata.height as usize))
+            }
+            OutputFormat::Pfm => {
+                let mut file = BufReader::new(File::open(path).map_err(LoadError::Io)?);
+                let (width, height, _) = read_pfm_header(&mut file)?;
+                Ok((width, height))
+            }
+            _ => {
+                let (width, height) = image::image_dimensions(path).map_err(LoadError::Image)?;
+                Ok((width as usize, height as usize))
+            }
+        }
+    }
+
+    // Colors have to be in `color_space` already
+    fn save_hdr_format(
+        &self,
+        path: impl AsRef<Path>,
+        format: OutputFormat,
+        color_space: ColorSpace,
+    ) -> Result<(), SaveError> {
+        match format {
+            OutputFormat::Exr { half_float } => self.save_exr(path, half_float, color_space),
+            OutputFormat::Hdr => self.save_hdr(path),
+            OutputFormat::Pfm => self.save_pfm(path),
+            _ => unreachable!("{format:?} isn't an HDR format"),
+        }
+    }
+
+    // The same image with linear colors in another color space
+    pub fn in_color_space(&self, color_space: ColorSpace) -> Image {
+        let mut image = self.clone();
+        if color_space != ColorSpace::Rec709 {
+            image
+                .pixels
+                .iter_mut()
+                .for_each(|pixel| *pixel = color_space.from_rec709(*pixel));
+        }
+        image
+    }
+
+    fn save_exr(
+        &self,
+        path: impl AsRef<Path>,
+        half_float: bool,
+        color_space: ColorSpace,
+    ) -> Result<(), SaveError> {
+        use exr::meta::attribute::Chromaticities;
+        use exr::prelude::*;
+
+        let color_channel = |name: &str, component: usize| {
+            let values = self.pixels.iter().map(|pixel| pixel[component]);
+            let samples = if half_float {
+                FlatSamples::F16(values.map(f16::from_f32).collect())
+            } else {
+                FlatSamples::F32(values.collect())
+            };
+            AnyChannel::new(name, samples)
+        };
+        let mut channels = vec![
+            color_channel("R", 0),
+            color_channel("G", 1),
+            color_channel("B", 2),
+        ];
+        channels.extend(self.extra_channels.iter().map(|channel| {
+            AnyChannel::new(
+                channel.name.as_str(),
+                FlatSamples::F32(channel.values.clone()),
+            )
+        }));
+
+        let layer = Layer::new(
+            (self.width, self.height),
+            LayerAttributes::default(),
+            Encoding::SMALL_LOSSLESS,
+            AnyChannels::sort(SmallVec::from_vec(channels)),
+        );
+        let mut image = exr::image::Image::from_layer(layer);
+        // Tell readers which primaries the values use
+        let [red, green, blue] = color_space.primaries().map(Vec2::from);
+        image.attributes.chromaticities = Some(Chromaticities {
+            red,
+            green,
+            blue,
+            white: Vec2::from(ColorSpace::WHITE_POINT),
+        });
+        image.write().to_file(path).map_err(SaveError::Exr)
+    }
+
+    // The R, G and B channels become the colors, the others extra channels
+    pub fn load_exr(path: impl AsRef<Path>) -> Result<Self, LoadError> {
+        use exr::prelude::*;
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_file(path)
+            .map_err(LoadError::Exr)?;
+        let layer = image.layer_data;
+        let mut result = Self::new(layer.size.width(), layer.size.height());
+        for channel in &layer.channel_data.list {
+            let values: Vec<f32> = channel.sample_data.values_as_f32().collect();
+            let component = match channel.name.to_string().as_str() {
+                "R" => 0,
+                "G" => 1,
+                "B" => 2,
+                name => {
+                    result.extra_channels.push(ExtraChannel {
+                        name: name.to_string(),
+                        values,
+                    });
+                    continue;
+                }
+            };
+            for (pixel, value) in result.pixels.iter_mut().zip(values) {
+                pixel[component] = value;
+            }
+        }
+        Ok(result)
+    }
+
+    fn save_hdr(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let file = BufWriter::new(File::create(path).map_err(SaveError::Io)?);
+        let pixels: Vec<Rgb<f32>> = self
+            .pixels
+            .iter()
+            .map(|pixel| Rgb([pixel.x, pixel.y, pixel.z]))
+            .collect();
+        HdrEncoder::new(file)
+            .encode(&pixels, self.width, self.height)
+            .map_err(SaveError::Image)
+    }
+
+    pub fn load_hdr(path: impl AsRef<Path>) -> Result<Image, LoadError> {
+        let file = BufReader::new(File::open(path).map_err(LoadError::Io)?);
+        let decoder = HdrDecoder::new(file).map_err(LoadError::Image)?;
+        let metadata = decoder.metadata();
+        let pixels = decoder.read_image_hdr().map_err(LoadError::Image)?;
+        let mut image = Image::new(metadata.width as usize, metadata.height as usize);
+        image.pixels = pixels
+            .into_iter()
+            .map(|Rgb([r, g, b])| Color::new(r, g, b))
+            .collect();
+        Ok(image)
+    }
+
+    // PFM stores rows from the bottom of the image, a negative scale means
+    // the floats are little endian
+    fn save_pfm(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let mut file = BufWriter::new(File::create(path).map_err(SaveError::Io)?);
+        let mut write = || -> io::Result<()> {
+            write!(file, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+            for row in self.pixels.chunks(self.width).rev() {
+                for pixel in row {
+                    for c in pixel.iter() {
+                        file.write_all(&c.to_le_bytes())?;
+                    }
+                }
+            }
+            file.flush()
+        };
+        write().map_err(SaveError::Io)
+    }
+
+    pub fn load_pfm(path: impl AsRef<Path>) -> Result<Image, LoadError> {
+        let invalid = |message: &str| LoadError::Invalid(message.to_string());
+        let mut file = BufReader::new(fs::File::open(path).map_err(LoadError::Io)?);
+        let (width, height, little_endian) = read_pfm_header(&mut file)?;
+
+        // Read what's there rather than allocating what the header claims
+        let bytes = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(3 * 4))
+            .ok_or_else(|| invalid("PFM image too large"))?;
+        let mut data = Vec::new();
+        file.take(bytes as u64)
+            .read_to_end(&mut data)
+            .map_err(LoadError::Io)?;
+        if data.len() != bytes {
+            return Err(invalid("truncated PFM image"));
+        }
+        let floats: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|bytes| {
+                let bytes = bytes.try_into().unwrap();
+                if little_endian {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                }
+            })
+            .collect();
+        let mut image = Image::new(width, height);
+        image.pixels = floats
+            .chunks_exact(width * 3)
+            .rev()
+            .flat_map(|row| row.chunks_exact(3))
+            .map(|c| Color::new(c[0], c[1], c[2]))
+            .collect();
+        Ok(image)
+    }
+}
+
+// Width, height and whether the floats are little endian. The header is three
+// whitespace separated tokens after the magic line
+fn read_pfm_header(file: &mut impl BufRead) -> Result<(usize, usize, bool), LoadError> {
+    let invalid = |message: &str| LoadError::Invalid(message.to_string());
+    let mut header = Vec::new();
+    let mut line = String::new();
+    while header.len() < 4 {
+        line.clear();
+        if file.read_line(&mut line).map_err(LoadError::Io)? == 0 {
+            return Err(invalid("truncated PFM header"));
+        }
+        header.extend(line.split_whitespace().map(str::to_string));
+    }
+    if header[0] != "PF" {
+        return Err(invalid("only color PFM images are supported"));
+    }
+    let size = |token: &str| match token.parse::<usize>() {
+        Ok(0) => Err(invalid("empty PFM image")),
+        Ok(size) => Ok(size),
+        Err(_) => Err(invalid("invalid PFM size")),
+    };
+    let (width, height) = (size(&header[1])?, size(&header[2])?);
+    let scale: f32 = header[3]
+        .parse()
+        .map_err(|_| invalid("invalid PFM scale"))?;
+    Ok((width, height, scale < 0.0))
+}
+
+fn ldr_format(format: OutputFormat) -> ImageFormat {
+    match format {
+        OutputFormat::Png => ImageFormat::Png,
+        OutputFormat::Jpeg => ImageFormat::Jpeg,
+        OutputFormat::Bmp => ImageFormat::Bmp,
+        OutputFormat::Tga => ImageFormat::Tga,
+        OutputFormat::Tiff => ImageFormat::Tiff,
+        _ => unreachable!("{format:?} isn't an LDR format"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A small image with values above 1 and a different value per pixel
+    fn test_image() -> Image {
+        let mut image = Image::new(5, 3);
+        for (i, pixel) in image.pixels.iter_mut().enumerate() {
+            let i = i as f32;
+            *pixel = Color::new(i * 0.37, 12.5 / (i + 1.0), 0.001 * i);
+        }
+        image
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("ray_tracing_{}_{name}", std::process::id()))
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let path = temp_path("round_trip.pfm");
+        let image = test_image();
+        image.save(&path, OutputFormat::Pfm).unwrap();
+        let loaded = Image::load_pfm(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (image.width, image.height));
+        assert_eq!(loaded.pixels, image.pixels);
+    }
+
+    #[test]
+    fn pfm_invalid_headers() {
+        let path = temp_path("invalid.pfm");
+        for contents in [
+            &b"PF\n0 3\n-1.0\n"[..],
+            b"PF\n1e30 1\n-1.0\n",
+            b"PF\n18446744073709551615 2\n-1.0\n",
+            b"PF\n100000 100000\n-1.0\n",
+            b"PF\n2 2\n-1.0\n\0\0\0\0",
+        ] {
+            fs::write(&path, contents).unwrap();
+            assert!(Image::load_pfm(&path).is_err(), "{contents:?}");
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hdr_round_trip() {
+        let path = temp_path("round_trip.hdr");
+        let image = test_image();
+        image.save(&path, OutputFormat::Hdr).unwrap();
+        let loaded = Image::load_hdr(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height), (image.width, image.height));
+        // RGBE keeps 8 bits of mantissa relative to the largest channel
+        for (loaded, original) in loaded.pixels.iter().zip(&image.pixels) {
+            let tolerance = original.max() / 128.0;
+            assert!(
+                (loaded - original).abs().max() <= tolerance,
+                "{loaded:?} != {original:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// The window showing the image while it renders. Without the `window` cargo
+// feature there is no window at all and every render is headless.
+
+#[cfg(feature = "window")]
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode};
+#[cfg(feature = "window")]
+use nalgebra_glm as glm;
+#[cfg(feature = "window")]
+use ray_tracing_in_one_we::OutputOptions;
+use ray_tracing_in_one_we::{CameraSettings, Framebuffer, Image, RenderSettings, ToneMapping};
+
+// Printed when the camera can be moved
+pub const CONTROLS: &str = "\
+WASD: move, Q/E: down/up, drag with the left button: look around
+Scroll: zoom, [/]: aperture, -/=: focus distance, F: focus on the target
+P: print the camera as a scene file section, ESC: stop and save";
+
+#[cfg(feature = "window")]
+pub struct Preview {
+    window: minifb::Window,
+    buffer: Vec<u32>,
+    // Screens are assumed to be sRGB, whatever the output color space is
+    options: OutputOptions,
+    // Mouse position when the camera was last turned, while dragging
+    drag_from: Option<(f32, f32)>,
+    // Whether the window was updated since the last `navigate`
+    polled: bool,
+}
+
+// Util function for minifb because it takes a specially formatted u32 for
+// colors
+#[cfg(feature = "window")]
+const fn from_u8_0rgb(r: u8, g: u8, b: u8) -> u32 {
+    let (r, g, b) = (r as u32, g as u32, b as u32);
+    (r << 16) | (g << 8) | b
+}
+
+#[cfg(not(feature = "window"))]
+pub enum Preview {}
+
+#[cfg(feature = "window")]
+impl Preview {
+    const UPDATE_RATE: u64 = 30; // FPS
+
+    pub fn open(settings: &RenderSettings, tone_mapping: ToneMapping) -> Result<Preview, String> {
+        let region = settings.region();
+        let mut window = minifb::Window::new(
+            "Raytracing - ESC to exit",
+            region.width,
+            region.height,
+            minifb::WindowOptions {
+                resize: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| err.to_string())?;
+        window.limit_update_rate(Some(std::time::Duration::from_micros(
+            1_000_000 / Self::UPDATE_RATE,
+        )));
+        Ok(Preview {
+            window,
+            buffer: vec![0; region.pixel_count()],
+            options: OutputOptions {
+                tone_mapping,
+                ..Default::default()
+            },
+            drag_from: None,
+            polled: false,
+        })
+    }
+
+    pub fn update(&mut self, framebuffer: &Framebuffer) {
+        for (i, color) in self.buffer.iter_mut().enumerate() {
+            let [r, g, b] = self.options.to_rgb8(framebuffer.mean(i));
+            *color = from_u8_0rgb(r, g, b);
+        }
+        self.show(framebuffer.width, framebuffer.height);
+    }
+
+    fn show(&mut self, width: usize, height: usize) {
+        self.window
+            .update_with_buffer(&self.buffer, width, height)
+            .unwrap();
+        self.polled = true;
+    }
+
+    // Read the inputs without redrawing the window
+    pub fn poll(&mut self) {
+        self.window.update();
+        self.polled = true;
+    }
+
+    // True once the user asked to stop, by closing the window or with ESC
+    pub fn should_close(&self) -> bool {
+        !self.window.is_open() || self.window.is_key_down(minifb::Key::Escape)
+    }
+
+    // Keep showing the image until the window is closed
+    pub fn wait_for_close(mut self, image: &Image) {
+        self.show_image(image);
+        while !self.should_close() {
+            self.show(image.width, image.height);
+        }
+    }
+
+    // Keep showing the image until the window is closed or the camera is
+    // moved. True if it was moved
+    pub fn wait_for_move(&mut self, image: &Image, camera: &mut CameraSettings) -> bool {
+        self.show_image(image);
+        while !self.should_close() {
+            self.show(image.width, image.height);
+            if self.navigate(camera) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn show_image(&mut self, image: &Image) {
+        for (color, [r, g, b]) in self.buffer.iter_mut().zip(image.to_rgb8(&self.options)) {
+            *color = from_u8_0rgb(r, g, b);
+        }
+    }
+
+    // Move the camera with what was pressed since the last update of the
+    // window. True if it moved
+    pub fn navigate(&mut self, camera: &mut CameraSettings) -> bool {
+        // Pressed keys stay down until the next update, they would move the
+        // camera again on every call
+        if !std::mem::take(&mut self.polled) {
+            return false;
+        }
+        let before = *camera;
+        let direction = camera.look_at - camera.look_from;
+        let forward = direction.normalize();
+        let right = forward.cross(&camera.vup).normalize();
+        let step = 0.05 * direction.norm();
+
+        for key in self.window.get_keys_pressed(KeyRepeat::Yes) {
+            let movement = match key {
+                Key::W => forward * step,
+                Key::S => -forward * step,
+                Key::D => right * step,
+                Key::A => -right * step,
+                Key::E => camera.vup * step,
+                Key::Q => -camera.vup * step,
+                _ => glm::Vec3::zeros(),
+            };
+            camera.look_from += movement;
+            camera.look_at += movement;
+            match key {
+                Key::RightBracket => camera.aperture += 0.05,
+                Key::LeftBracket => camera.aperture = (camera.aperture - 0.05).max(0.0),
+                Key::Equal => camera.focus_dist *= 1.1,
+                Key::Minus => camera.focus_dist /= 1.1,
+                Key::F => camera.focus_dist = direction.norm(),
+                Key::P => println!("\n{}", camera.to_toml()),
+                _ => {}
+            }
+        }
+
+        if let Some((_, scroll)) = self.window.get_scroll_wheel() {
+            camera.vfov = (camera.vfov * 0.95_f32.powf(scroll.signum())).clamp(1.0, 170.0);
+        }
+
+        // Turn around `look_from`, dragging across the window turns by about
+        // the field of view
+        if self.window.get_mouse_down(MouseButton::Left) {
+            let mouse = self.window.get_mouse_pos(MouseMode::Discard);
+            if let (Some((from_x, from_y)), Some((x, y))) = (self.drag_from, mouse) {
+                let (_, height) = self.window.get_size();
+                let degrees_per_pixel = camera.vfov / height as f32;
+                let yaw = (x - from_x) * degrees_per_pixel;
+                let pitch = (y - from_y) * degrees_per_pixel;
+                let direction = camera.look_at - camera.look_from;
+                let direction = glm::rotate_vec3(&direction, -yaw.to_radians(), &camera.vup);
+                let turned = glm::rotate_vec3(&direction, -pitch.to_radians(), &right);
+                // Stop before looking straight up or down, the camera would flip
+                let direction = if turned.normalize().dot(&camera.vup.normalize()).abs() < 0.99 {
+                    turned
+                } else {
+                    direction
+                };
+                camera.look_at = camera.look_from + direction;
+            }
+            self.drag_from = mouse;
+        } else {
+            self.drag_from = None;
+        }
+
+        *camera != before
+    }
+}
+
+#[cfg(not(feature = "window"))]
+impl Preview {
+    pub fn open(_settings: &RenderSettings, _tone_mapping: ToneMapping) -> Result<Preview, String> {
+        Err("compiled without the `window` feature".to_string())
+    }
+
+    pub fn update(&mut self, _framebuffer: &Framebuffer) {
+        match *self {}
+    }
+
+    pub fn should_close(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn poll(&mut self) {
+        match *self {}
+    }
+
+    pub fn wait_for_close(self, _image: &Image) {
+        match self {}
+    }
+
+    pub fn wait_for_move(&mut self, _image: &Image, _camera: &mut CameraSettings) -> bool {
+        match *self {}
+    }
+
+    pub fn navigate(&mut self, _camera: &mut CameraSettings) -> bool {
+        match *self {}
+    }
+}
@@ -0,0 +1,479 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use rayon::prelude::*;
+
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::checkpoint::Checkpoint;
+use crate::color::luminance;
+use crate::filter::Filter;
+use crate::framebuffer::{Framebuffer, SampleStats};
+use crate::hittable::Hittable;
+use crate::hittable_list::HittableList;
+use crate::material::ScatterResponse;
+use crate::noise::Noise;
+use crate::output::Image;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::settings::{AdaptiveSettings, RenderSettings};
+use crate::sky::Sky;
+use crate::space_filler::TraversalOrder;
+use crate::tile::{self, Tile};
+use crate::Color;
+
+// Everything needed to render an image
+pub struct Scene {
+    pub world: Bvh,
+    pub camera: Camera,
+    pub sky: Sky,
+}
+
+impl Scene {
+    pub fn new(world: HittableList, camera: Camera, sky: Sky) -> Self {
+        Self {
+            world: Bvh::new(world),
+            camera,
+            sky,
+        }
+    }
+}
+
+// Render a scene in one call
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Image {
+    Renderer::new(settings.clone()).render(scene)
+}
+
+// Renders scenes with the same settings. What only depends on the settings is
+// computed once, when the renderer is created
+pub struct Renderer {
+    settings: RenderSettings,
+    // Only used by the blue noise sampler
+    noise: Option<Noise>,
+    // Tiles of the padded region, in rendering order
+    tiles: Vec<Tile>,
+    // Tiles of the passes of progressive renders, always in the same order
+    pass_tiles: Vec<Tile>,
+}
+
+impl Renderer {
+    // Panics if the crop doesn't fit in the image
+    pub fn new(settings: RenderSettings) -> Self {
+        if let Some(crop) = settings.crop {
+            assert!(
+                crop.fits_in(settings.width, settings.height),
+                "The crop {crop:?} doesn't fit in the {}x{} image",
+                settings.width,
+                settings.height
+            );
+        }
+        Self {
+            noise: (settings.sampler == SamplerKind::BlueNoise).then(|| Noise::new(&settings)),
+            tiles: tile::tiles(&settings.padded_region(), settings.order, settings.seed),
+            pass_tiles: tile::tiles(&settings.padded_region(), TraversalOrder::Scanline, 0),
+            settings,
+        }
+    }
+
+    pub fn settings(&self) -> &RenderSettings {
+        &self.settings
+    }
+
+    pub fn render(&self, scene: &Scene) -> Image {
+        self.render_with_progress(scene, |_, _| true)
+            .expect("The render can't be interrupted without a progress callback")
+            .to_image()
+    }
+
+    // Render the scene, calling `on_progress` on the calling thread after each
+    // tile with the samples so far and the fraction of the pixels done. The
+    // render stops, returning None, as soon as `on_progress` returns false
+    pub fn render_with_progress(
+        &self,
+        scene: &Scene,
+        mut on_progress: impl FnMut(&Framebuffer, f32) -> bool,
+    ) -> Option<Framebuffer> {
+        let settings = &self.settings;
+        let (region, padded) = (settings.region(), settings.padded_region());
+        let reach = settings.filter.reach() as isize;
+        let offset = |tile: &Tile| {
+            (
+                (tile.x - region.x) as isize - reach,
+                (tile.y - region.y) as isize - reach,
+            )
+        };
+        let mut framebuffer = Framebuffer::new(region.width, region.height);
+        let mut rendered = Vec::new();
+        let cancelled = &AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|s| {
+            s.spawn(move || {
+                self.tiles.par_iter().enumerate().for_each(|(i, tile)| {
+                    if cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let rendered = self.render_tile(tile, scene);
+                    if sender.send((i, rendered)).is_err() {}
+                });
+            });
+            // Tiles are added as soon as they're done to show the progress
+            let mut pixels_done = 0;
+            for (i, buffer) in receiver.iter() {
+                let tile = &self.tiles[i];
+                let (x, y) = offset(tile);
+                framebuffer.merge(&buffer, x, y);
+                if reach > 0 {
+                    rendered.push((tile, buffer));
+                }
+                pixels_done += tile.pixel_count();
+                let progress = pixels_done as f32 / padded.pixel_count() as f32;
+                if !on_progress(&framebuffer, progress) {
+                    cancelled.store(true, Ordering::Relaxed);
+                    return None;
+                }
+            }
+            Some(())
+        })?;
+        // Where tiles overlap the sums depend on the order they're added in,
+        // the image adds them in the same order whatever order they were done
+        if reach > 0 {
+            rendered.sort_by_key(|(tile, _)| (tile.y, tile.x));
+            framebuffer = Framebuffer::new(region.width, region.height);
+            for (tile, buffer) in &rendered {
+                let (x, y) = offset(tile);
+                framebuffer.merge(buffer, x, y);
+            }
+        }
+        Some(framebuffer)
+    }
+
+    // Render the pixels of a tile in its own buffer, with room around it for
+    // the samples splatted on neighbor pixels
+    fn render_tile(&self, tile: &Tile, scene: &Scene) -> Framebuffer {
+        let settings = &self.settings;
+        let reach = settings.filter.reach();
+        let mut buffer = Framebuffer::new(tile.width + 2 * reach, tile.height + 2 * reach);
+        let origin = (
+            tile.x as isize - reach as isize,
+            tile.y as isize - reach as isize,
+        );
+        let mut sampler = self.sampler();
+        let mut splat = PixelSplat::new(&settings.filter);
+        for (x, y) in tile.pixels() {
+            let samples = 0..settings.max_samples_per_pixel();
+            pixel_processing(x, y, samples, scene, settings, sampler.as_mut(), &mut splat);
+            splat.add_to(&mut buffer, origin);
+        }
+        buffer
+    }
+
+    // Render the whole frame one sample per pixel at a time. `on_progress` is
+    // called on the calling thread after each tile with the fraction of the
+    // render done, and with the samples so far once a pass ends. Returning
+    // false stops the render, keeping the samples of the passes done
+    pub fn render_progressive(
+        &self,
+        scene: &Scene,
+        mut on_progress: impl FnMut(Option<&Framebuffer>, f32) -> bool,
+    ) -> Framebuffer {
+        let start = Checkpoint::new(&self.settings);
+        self.resume_progressive(scene, start, |checkpoint, progress| {
+            let framebuffer = checkpoint.map(|checkpoint| checkpoint.cropped(&self.settings));
+            on_progress(framebuffer.as_deref(), progress)
+        })
+        .cropped(&self.settings)
+        .into_owned()
+    }
+
+    // Continue a progressive render from a checkpoint of it, made with the
+    // same settings and scene. `on_progress` gets a checkpoint after each pass
+    pub fn resume_progressive(
+        &self,
+        scene: &Scene,
+        mut checkpoint: Checkpoint,
+        mut on_progress: impl FnMut(Option<&Checkpoint>, f32) -> bool,
+    ) -> Checkpoint {
+        let settings = &self.settings;
+        let region = settings.padded_region();
+        let reach = settings.filter.reach() as isize;
+        let passes = settings.max_samples_per_pixel();
+        for pass in checkpoint.passes..passes {
+            let tiles = &self.pass_tiles;
+            let mut rendered: Vec<Option<Framebuffer>> = vec![None; tiles.len()];
+            let cancelled = &AtomicBool::new(false);
+            let framebuffer = &checkpoint.framebuffer;
+            let finished = thread::scope(|s| {
+                let (sender, receiver) = mpsc::channel();
+                s.spawn(move || {
+                    tiles.par_iter().enumerate().for_each(|(i, tile)| {
+                        if cancelled.load(Ordering::Relaxed) {
+                            return;
+                        }
+                        let buffer = self.render_tile_pass(tile, scene, framebuffer);
+                        if sender.send((i, buffer)).is_err() {}
+                    });
+                });
+                // Called between tiles too, so a pass can be stopped midway
+                let mut pixels_done = 0;
+                for (i, buffer) in receiver.iter() {
+                    rendered[i] = buffer;
+                    pixels_done += tiles[i].pixel_count();
+                    let pass_done = pixels_done as f32 / region.pixel_count() as f32;
+                    if !on_progress(None, (pass as f32 + pass_done) / passes as f32) {
+                        cancelled.store(true, Ordering::Relaxed);
+                        return false;
+                    }
+                }
+                true
+            });
+            // Stopped midway, or every pixel converged
+            if !finished || rendered.iter().all(Option::is_none) {
+                break;
+            }
+            // Tiles overlap where the filter reaches past them, they're
+            // always added in the same order for reproductible sums
+            for (tile, buffer) in tiles.iter().zip(&rendered) {
+                if let Some(buffer) = buffer {
+                    checkpoint.framebuffer.merge(
+                        buffer,
+                        (tile.x - region.x) as isize - reach,
+                        (tile.y - region.y) as isize - reach,
+                    );
+                }
+            }
+            checkpoint.passes = pass + 1;
+            let progress = checkpoint.passes as f32 / passes as f32;
+            if !on_progress(Some(&checkpoint), progress) {
+                break;
+            }
+        }
+        checkpoint
+    }
+
+    // One more sample for each pixel of the tile that needs it, given the
+    // samples so far in `framebuffer`, covering the padded region. The tile is
+    // rendered in its own buffer, with room around it for the samples splatted
+    // on neighbor pixels. None if no pixel of the tile needed samples
+    fn render_tile_pass(
+        &self,
+        tile: &Tile,
+        scene: &Scene,
+        framebuffer: &Framebuffer,
+    ) -> Option<Framebuffer> {
+        let settings = &self.settings;
+        let region = settings.padded_region();
+        let reach = settings.filter.reach();
+        let mut buffer = Framebuffer::new(tile.width + 2 * reach, tile.height + 2 * reach);
+        let origin = (
+            tile.x as isize - reach as isize,
+            tile.y as isize - reach as isize,
+        );
+        let mut sampler = self.sampler();
+        let mut splat = PixelSplat::new(&settings.filter);
+        let mut sampled = false;
+        for (x, y) in tile.pixels() {
+            let stats = framebuffer.stats(x - region.x + (y - region.y) * region.width);
+            if !needs_samples(settings, stats) {
+                continue;
+            }
+            let sample = stats.count as usize;
+            let samples = sample..sample + 1;
+            pixel_processing(x, y, samples, scene, settings, sampler.as_mut(), &mut splat);
+            splat.add_to(&mut buffer, origin);
+            sampled = true;
+        }
+        sampled.then_some(buffer)
+    }
+
+    // Samplers only depend on the pixel and sample they're started at, one is
+    // reused for every pixel of a tile
+    fn sampler(&self) -> Box<dyn Sampler + '_> {
+        let settings = &self.settings;
+        settings.sampler.sampler(
+            settings.seed,
+            settings.max_samples_per_pixel(),
+            self.noise.as_ref(),
+        )
+    }
+}
+
+// The samples of a pixel, splatted onto the pixels up to `reach` pixels away
+// from it. One is reused for every pixel of a tile, so the hot loop doesn't
+// allocate
+struct PixelSplat {
+    x: usize,
+    y: usize,
+    reach: usize,
+    // Row by row, (2 * reach + 1)² of them
+    sums: Vec<Color>,
+    weights: Vec<f32>,
+    // Weights of the current sample along x
+    weights_x: Vec<f32>,
+    stats: SampleStats,
+}
+
+impl PixelSplat {
+    fn new(filter: &Filter) -> Self {
+        let reach = filter.reach();
+        let side = 2 * reach + 1;
+        Self {
+            x: 0,
+            y: 0,
+            reach,
+            sums: vec![Color::zeros(); side * side],
+            weights: vec![0.0; side * side],
+            weights_x: vec![0.0; side],
+            stats: SampleStats::default(),
+        }
+    }
+
+    // Start over with the pixel at column `x` and row `y`
+    fn reset(&mut self, x: usize, y: usize) {
+        (self.x, self.y) = (x, y);
+        self.sums.fill(Color::zeros());
+        self.weights.fill(0.0);
+        self.stats = SampleStats::default();
+    }
+
+    // Splat a sample `(dx, dy)` pixels away from the center of the pixel
+    fn add_sample(&mut self, filter: &Filter, dx: f32, dy: f32, color: Color) {
+        self.stats.add(luminance(&color));
+        let reach = self.reach as isize;
+        let side = 2 * self.reach + 1;
+        for (weight_x, ox) in self.weights_x.iter_mut().zip(-reach..=reach) {
+            *weight_x = filter.weight_1d(dx - ox as f32);
+        }
+        for (row, oy) in (-reach..=reach).enumerate() {
+            let weight_y = filter.weight_1d(dy - oy as f32);
+            if weight_y == 0.0 {
+                continue;
+            }
+            for (column, weight_x) in self.weights_x.iter().enumerate() {
+                let weight = weight_x * weight_y;
+                self.sums[row * side + column] += color * weight;
+                self.weights[row * side + column] += weight;
+            }
+        }
+    }
+
+    // `origin` is where the top left corner of `framebuffer` is in the image
+    fn add_to(&self, framebuffer: &mut Framebuffer, origin: (isize, isize)) {
+        let (x, y) = (self.x as isize - origin.0, self.y as isize - origin.1);
+        let side = 2 * self.reach + 1;
+        for row in 0..side {
+            let Ok(y) = usize::try_from(y + row as isize - self.reach as isize) else {
+                continue;
+            };
+            for column in 0..side {
+                let Ok(x) = usize::try_from(x + column as isize - self.reach as isize) else {
+                    continue;
+                };
+                let weight = self.weights[row * side + column];
+                if x < framebuffer.width && y < framebuffer.height && weight != 0.0 {
+                    let index = x + y * framebuffer.width;
+                    framebuffer.splat(index, self.sums[row * side + column], weight);
+                }
+            }
+        }
+        framebuffer.add_stats(x as usize + y as usize * framebuffer.width, &self.stats);
+    }
+}
+
+// Samples taken between two checks of the noise of a pixel in adaptive mode,
+// stopping right after a lucky sample would bias the image
+const CONVERGENCE_CHECK_INTERVAL: usize = 8;
+
+fn converged(adaptive: &AdaptiveSettings, stats: &SampleStats) -> bool {
+    let count = stats.count as usize;
+    count >= adaptive.min_samples
+        && count.is_multiple_of(CONVERGENCE_CHECK_INTERVAL)
+        && stats.encoded_error() < adaptive.noise_threshold
+}
+
+// Whether a pixel with these samples needs more of them
+fn needs_samples(settings: &RenderSettings, stats: &SampleStats) -> bool {
+    let count = stats.count as usize;
+    match &settings.adaptive {
+        Some(adaptive) => count < adaptive.max_samples && !converged(adaptive, stats),
+        None => count < settings.samples_per_pixel,
+    }
+}
+
+// Trace the `samples` of the pixel at column `x` and row `y`, from the top,
+// into `splat`. Stops early once the pixel converged in adaptive mode
+fn pixel_processing(
+    x: usize,
+    y: usize,
+    samples: Range<usize>,
+    scene: &Scene,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+    splat: &mut PixelSplat,
+) {
+    splat.reset(x, y);
+    let j = settings.height - y;
+    for s in samples {
+        // Render
+        sampler.start_sample(x + y * settings.width, s);
+        let rand = sampler.pixel_2d();
+        let (rand_x, rand_y) = (rand.x, rand.y);
+        let u = (x as f32 + rand_x) / (settings.width - 1) as f32;
+        let v = (j as f32 + rand_y) / (settings.height - 1) as f32;
+        let ray = scene.camera.get_ray(u, v, sampler);
+        let color = ray_color(ray, scene, settings, sampler);
+        // v goes up while rows go down
+        splat.add_sample(&settings.filter, rand_x - 0.5, 0.5 - rand_y, color);
+
+        if !needs_samples(settings, &splat.stats) {
+            break;
+        }
+    }
+}
+
+fn ray_color(
+    ray: Ray,
+    scene: &Scene,
+    settings: &RenderSettings,
+    sampler: &mut dyn Sampler,
+) -> Color {
+    use tailcall::tailcall;
+    #[tailcall]
+    fn _ray_color(
+        ray: Ray,
+        scene: &Scene,
+        sampler: &mut dyn Sampler,
+        depth: usize,
+        accumulator: Color,
+    ) -> Color {
+        if depth == 0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        if let Some(rec) = scene.world.hit(&ray, 0.001, f32::INFINITY) {
+            match rec.material.scatter(&ray, &rec, sampler) {
+                ScatterResponse::Scatter(attenuation, scattered) => {
+                    return _ray_color(
+                        scattered,
+                        scene,
+                        sampler,
+                        depth - 1,
+                        attenuation.component_mul(&accumulator),
+                    );
+                }
+                ScatterResponse::Absorb(absorbtion) => {
+                    return absorbtion.component_mul(&accumulator);
+                }
+            }
+        }
+
+        scene.sky.color(&ray).component_mul(&accumulator)
+    }
+    _ray_color(
+        ray,
+        scene,
+        sampler,
+        settings.max_depth,
+        Color::new(1.0, 1.0, 1.0),
+    )
+}
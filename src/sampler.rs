@@ -0,0 +1,423 @@
+// Random numbers used while tracing a path. A path takes its numbers one
+// dimension after the other: the position in the pixel first, then the lens
+// and then whatever each bounce needs. Samplers only differ in how those
+// numbers are spread: independent ones are plain random numbers while the
+// others are spread more evenly across the samples of a pixel so the image
+// converges faster.
+//
+// Every sampler is deterministic: numbers only depend on the seed, the pixel,
+// the sample index and the dimension, so results don't depend on which thread
+// renders what and no two pixels share their numbers.
+
+use crate::noise::Noise;
+
+pub trait Sampler {
+    // Start the path of a new sample of a pixel
+    fn start_sample(&mut self, pixel: usize, sample: usize);
+
+    // A uniform number in [0, 1)
+    fn next_1d(&mut self) -> f32;
+
+    fn next_2d(&mut self) -> glm::Vec2;
+
+    // Position of the sample inside its pixel, always the first two
+    // dimensions of a path
+    fn pixel_2d(&mut self) -> glm::Vec2 {
+        self.next_2d()
+    }
+}
+
+// The samplers that can be picked for a render
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    // Blue noise pixel positions, independent numbers for the rest of the
+    // path
+    #[default]
+    BlueNoise,
+    Independent,
+    // Jittered samples, with each dimension cut in as many strata as there
+    // are samples in a pixel
+    Stratified,
+    // The Halton sequence with randomly permuted digits
+    Halton,
+    // The Sobol sequence with Owen scrambling
+    Sobol,
+}
+
+impl SamplerKind {
+    // `noise` is only needed by the blue noise sampler. The noise depends on
+    // the render settings, it's made by the renderer
+    pub(crate) fn sampler<'a>(
+        &self,
+        seed: u64,
+        samples_per_pixel: usize,
+        noise: Option<&'a Noise>,
+    ) -> Box<dyn Sampler + 'a> {
+        match self {
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler {
+                noise: noise.expect("The blue noise sampler needs the noise of the render"),
+                rest: IndependentSampler::new(seed),
+            }),
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                samples_per_pixel,
+                state: SampleState::new(seed),
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler {
+                state: SampleState::new(seed),
+            }),
+            SamplerKind::Sobol => Box::new(SobolSampler {
+                state: SampleState::new(seed),
+            }),
+        }
+    }
+}
+
+// Where a sampler is in the path of the current sample
+struct SampleState {
+    seed: u64,
+    pixel: u64,
+    sample: u64,
+    dimension: u64,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel: 0,
+            sample: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.pixel = pixel as u64;
+        self.sample = sample as u64;
+        self.dimension = 0;
+    }
+
+    // Move to the next dimension, returning the current one
+    fn next_dimension(&mut self) -> u64 {
+        self.dimension += 1;
+        self.dimension - 1
+    }
+
+    // Random bits shared by every sample of the pixel, different for each
+    // dimension
+    fn pixel_hash(&self, dimension: u64) -> u64 {
+        hash(&[self.seed, self.pixel, dimension])
+    }
+
+    // Random bits for one dimension of one sample
+    fn sample_hash(&self, dimension: u64) -> u64 {
+        hash(&[self.seed, self.pixel, self.sample, dimension])
+    }
+}
+
+pub struct IndependentSampler {
+    state: SampleState,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            state: SampleState::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.state.start_sample(pixel, sample);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        to_unit_f32(self.state.sample_hash(dimension))
+    }
+
+    fn next_2d(&mut self) -> glm::Vec2 {
+        glm::vec2(self.next_1d(), self.next_1d())
+    }
+}
+
+pub struct BlueNoiseSampler<'a> {
+    noise: &'a Noise,
+    rest: IndependentSampler,
+}
+
+impl Sampler for BlueNoiseSampler<'_> {
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.rest.start_sample(pixel, sample);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        self.rest.next_1d()
+    }
+
+    fn next_2d(&mut self) -> glm::Vec2 {
+        self.rest.next_2d()
+    }
+
+    // The pattern is shifted by a random offset in each pixel, wrapping
+    // around its edges, so neighbor pixels don't have the same pattern
+    fn pixel_2d(&mut self) -> glm::Vec2 {
+        let state = &mut self.rest.state;
+        let dimension = state.next_dimension();
+        state.next_dimension();
+        let shift_hash = state.pixel_hash(dimension);
+        let shift = glm::vec2(to_unit_f32(shift_hash), to_unit_f32(mix(shift_hash)));
+        let sample = state.sample as usize;
+        let point = glm::vec2(self.noise.randx(sample), self.noise.randy(sample)) + shift;
+        point.map(|c| c.fract().min(ONE_MINUS_EPSILON))
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    state: SampleState,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.state.start_sample(pixel, sample);
+    }
+
+    // Each sample of the pixel gets its own stratum, picked by a random
+    // permutation so the dimensions aren't correlated
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        let count = self.samples_per_pixel as u32;
+        let stratum = permute(
+            self.state.sample as u32 % count,
+            count,
+            self.state.pixel_hash(dimension) as u32,
+        );
+        let jitter = to_unit_f32(self.state.sample_hash(dimension));
+        ((stratum as f32 + jitter) / count as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    // Correlated multi-jittered sampling from Andrew Kensler: samples are
+    // stratified in 2D and their projections on each axis too
+    fn next_2d(&mut self) -> glm::Vec2 {
+        let dimension = self.state.next_dimension();
+        self.state.next_dimension();
+        let count = self.samples_per_pixel as u32;
+        let m = (count as f32).sqrt().ceil() as u32;
+        let n = count.div_ceil(m);
+        let p = self.state.pixel_hash(dimension) as u32;
+
+        let s = permute(
+            self.state.sample as u32 % count,
+            count,
+            p.wrapping_mul(0x51633e2d),
+        );
+        let sx = permute(s % m, m, p.wrapping_mul(0xa511e9b3));
+        let sy = permute(s / m, n, p.wrapping_mul(0x63d83595));
+        let jitter_hash = self.state.sample_hash(dimension);
+        let (jx, jy) = (to_unit_f32(jitter_hash), to_unit_f32(mix(jitter_hash)));
+        glm::vec2(
+            ((s % m) as f32 + (sy as f32 + jx) / n as f32) / m as f32,
+            ((s / m) as f32 + (sx as f32 + jy) / m as f32) / n as f32,
+        )
+        .map(|x| x.min(ONE_MINUS_EPSILON))
+    }
+}
+
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    // Bases of the first dimensions. Past them the sequence gets correlated
+    // between dimensions so the sampler switches to random numbers
+    const PRIMES: [u32; 64] = [
+        2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+        97, 101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181,
+        191, 193, 197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281,
+        283, 293, 307, 311,
+    ];
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.state.start_sample(pixel, sample);
+    }
+
+    fn next_1d(&mut self) -> f32 {
+        let dimension = self.state.next_dimension();
+        let Some(&base) = Self::PRIMES.get(dimension as usize) else {
+            return to_unit_f32(self.state.sample_hash(dimension));
+        };
+        scrambled_radical_inverse(base, self.state.sample, self.state.pixel_hash(dimension))
+    }
+
+    fn next_2d(&mut self) -> glm::Vec2 {
+        glm::vec2(self.next_1d(), self.next_1d())
+    }
+}
+
+// Mirror the digits of `index` in `base` around the decimal point, each digit
+// position going through its own random permutation
+fn scrambled_radical_inverse(base: u32, mut index: u64, seed: u64) -> f32 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    // Keep going past the last digit of the index, its zeros are permuted too
+    let mut digit_position = 0;
+    while scale > 1e-8 {
+        let digit = (index % base as u64) as u32;
+        let permuted = permute(digit, base, hash(&[seed, digit_position]) as u32);
+        result += permuted as f64 * scale;
+        index /= base as u64;
+        scale *= inverse_base;
+        digit_position += 1;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Owen scrambled Sobol points, following Brent Burley's "Practical Hash-based
+// Owen Scrambling". Every pair of dimensions is a 2D Sobol pattern and the
+// order of the samples is shuffled differently for each pair, so the pairs
+// don't correlate with each other
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    fn sample(&mut self) -> (u32, u32) {
+        let dimension = self.state.next_dimension();
+        self.state.next_dimension();
+        let seed = self.state.pixel_hash(dimension);
+        let index = nested_uniform_scramble(self.state.sample as u32, seed as u32);
+        let (x, y) = sobol_2d(index);
+        (
+            nested_uniform_scramble(x, mix(seed) as u32),
+            nested_uniform_scramble(y, mix(mix(seed)) as u32),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: usize, sample: usize) {
+        self.state.start_sample(pixel, sample);
+    }
+
+    // A whole pair of dimensions is used, but the sampler never had enough
+    // dimensions to be worth saving the second one
+    fn next_1d(&mut self) -> f32 {
+        let (x, _) = self.sample();
+        to_unit_f32((x as u64) << 32)
+    }
+
+    fn next_2d(&mut self) -> glm::Vec2 {
+        let (x, y) = self.sample();
+        glm::vec2(to_unit_f32((x as u64) << 32), to_unit_f32((y as u64) << 32))
+    }
+}
+
+// The first two dimensions of the Sobol sequence as 32 bits fractions: the
+// Van der Corput sequence and the one with the x + 1 primitive polynomial
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut y = 0;
+    let mut direction = 1 << 31;
+    let mut bits = index;
+    while bits != 0 {
+        if bits & 1 != 0 {
+            y ^= direction;
+        }
+        bits >>= 1;
+        direction ^= direction >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+// Owen scrambling: flip each bit depending on the bits above it
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Random permutation of the integers below `length`, from Andrew Kensler's
+// "Correlated Multi-Jittered Sampling"
+fn permute(mut i: u32, length: u32, p: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % length
+}
+
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+// The 24 high bits fill the mantissa of a f32
+fn to_unit_f32(bits: u64) -> f32 {
+    (bits >> 40) as f32 / (1u64 << 24) as f32
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(mix(values.len() as u64), |hash, value| {
+        mix(hash ^ value.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+// Finalizer of splitmix64, every bit of the input affects every bit of the
+// output
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// Uniformly distributed point on the unit sphere
+pub fn random_unit_vector(sampler: &mut dyn Sampler) -> glm::Vec3 {
+    let u = sampler.next_2d();
+    let z = 1.0 - 2.0 * u.x;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = std::f32::consts::TAU * u.y;
+    glm::vec3(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Uniformly distributed point in the unit disk, in the xy plane
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> glm::Vec3 {
+    let u = sampler.next_2d();
+    let r = u.x.sqrt();
+    let phi = std::f32::consts::TAU * u.y;
+    glm::vec3(r * phi.cos(), r * phi.sin(), 0.0)
+}
@@ -1,6 +1,8 @@
 #![allow(unused)]
 
 use crate::material::*;
+use crate::settings::RenderSettings;
+use crate::sky::Sky;
 use crate::sphere::*;
 use crate::*;
 
@@ -8,11 +10,16 @@ use crate::*;
 pub const OUTPUT_NAME: &str = "scene_output.png";
 
 // Rendering settings
-pub const ASPECT_RATIO: f32 = 3.0 / 2.0;
-pub const WIDTH: usize = 1200;
-pub const HEIGHT: usize = (WIDTH as f32 / ASPECT_RATIO) as usize;
-pub const SAMPLE_PER_PIXEL: usize = 500;
-pub const MAX_DEPTH: usize = 50;
+pub fn render_settings() -> RenderSettings {
+    RenderSettings {
+        width: 1200,
+        height: 800,
+        samples_per_pixel: 500,
+        max_depth: 50,
+        seed: SEED,
+        ..Default::default()
+    }
+}
 
 pub const MATERIAL_GROUND: Lambertian = Lambertian::new(Color::new(0.5, 0.5, 0.5));
 // pub const MATERIAL_CENTER: Lambertian = Lambertian::new(Color::new(0.1, 0.2,
@@ -33,8 +40,8 @@ pub fn rand_vec_between(r: &mut impl Rng, min: f32, max: f32) -> glm::Vec3 {
     )
 }
 
-pub fn init_world_and_camera() {
-    let mut r = StdRng::seed_from_u64(SEED);
+pub fn build_scene(settings: &RenderSettings) -> Scene {
+    let mut r = StdRng::seed_from_u64(settings.seed);
     let mut world = HittableList::default();
     world.add(Sphere::new(
         glm::vec3(0.0, -1000.0, 0.0),
@@ -76,10 +83,6 @@ pub fn init_world_and_camera() {
     let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Sphere::new(glm::vec3(4.0, 1.0, 0.0), 1.0, material3));
 
-    if WORLD.set(world).is_err() {
-        panic!("Tried to set WORLD twice. This is a bug");
-    }
-
     let look_from = glm::vec3(13.0, 2.0, 3.0);
     let look_at = glm::vec3(0.0, 0.0, 0.0);
     let v_up = glm::vec3(0.0, 1.0, 0.0);
@@ -90,18 +93,15 @@ pub fn init_world_and_camera() {
         look_at,
         v_up,
         20.0,
-        ASPECT_RATIO,
+        settings.aspect_ratio(),
         aperture,
         dist_to_focus,
     );
-    if CAMERA.set(camera).is_err() {
-        panic!("Tried to set CAMERA twice. This is a bug");
-    }
+    Scene::new(world, camera, SKY_COLOR)
 }
 
 // Sky color
-pub fn sky_color(ray: &Ray, _depth: usize) -> Color {
-    let unit_dir = ray.dir.normalize();
-    let t = 0.5 * (unit_dir.y + 1.0);
-    Color::new(1.00, 1.00, 1.00).lerp(&Color::new(0.5, 0.7, 1.0), t)
-}
+pub const SKY_COLOR: Sky = Sky::Gradient {
+    bottom: Color::new(1.00, 1.00, 1.00),
+    top: Color::new(0.5, 0.7, 1.0),
+};
@@ -0,0 +1,512 @@
+// Declarative scene files. A scene is described in TOML, see
+// `scenes/example.toml` for an example of every supported section.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hasher;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::camera::Camera;
+use crate::checkpoint::StableHasher;
+use crate::hittable_list::HittableList;
+use crate::material::*;
+use crate::renderer::Scene;
+use crate::settings::RenderSettings;
+use crate::sky::Sky;
+use crate::sphere::Sphere;
+use crate::triangle::Triangle;
+use crate::{obj, Color};
+
+#[derive(Debug)]
+pub struct SceneError {
+    pub path: PathBuf,
+    // Line of the offending statement, starting at 1
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.path.display(), self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+// A parsed scene file. The render settings can still be changed before
+// building the scene
+pub struct SceneDescription {
+    pub settings: RenderSettings,
+    pub output: Option<String>,
+    path: PathBuf,
+    source: String,
+    file: SceneFile,
+}
+
+// Missing settings keep their default value
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderSection {
+    width: Option<Spanned<usize>>,
+    height: Option<Spanned<usize>>,
+    samples_per_pixel: Option<Spanned<usize>>,
+    max_depth: Option<Spanned<usize>>,
+    seed: Option<u64>,
+    output: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    render: RenderSection,
+    camera: CameraSection,
+    #[serde(default)]
+    sky: Option<SkySection>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialSection>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectSection>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraSection {
+    look_from: [f32; 3],
+    look_at: [f32; 3],
+    #[serde(default = "default_vup")]
+    vup: [f32; 3],
+    vfov: f32,
+    #[serde(default)]
+    aperture: f32,
+    // Defaults to the distance between `look_from` and `look_at`
+    focus_dist: Option<f32>,
+}
+
+fn default_vup() -> [f32; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+// The keys of the sky, material and object sections depend on their `type`.
+// Serde loses the position of bad values in internally tagged enums, so these
+// sections take the keys of every type and are checked against their type
+// when the scene is built
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkySection {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    // `solid`
+    color: Option<Spanned<[f32; 3]>>,
+    // `gradient`
+    bottom: Option<Spanned<[f32; 3]>>,
+    top: Option<Spanned<[f32; 3]>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialSection {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    // `lambertian` and `metal`
+    albedo: Option<Spanned<[f32; 3]>>,
+    // `metal`, 0 by default
+    fuzz: Option<Spanned<f32>>,
+    // `dielectric`
+    refraction_i: Option<Spanned<f32>>,
+    // `light`
+    color: Option<Spanned<[f32; 3]>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectSection {
+    #[serde(rename = "type")]
+    kind: Spanned<String>,
+    // `sphere`
+    center: Option<Spanned<[f32; 3]>>,
+    radius: Option<Spanned<f32>>,
+    // `triangle`
+    vertices: Option<Spanned<[[f32; 3]; 3]>>,
+    // `obj`, relative to the scene file
+    path: Option<Spanned<String>>,
+    // Every type, optional for `obj` where it replaces the materials of the
+    // MTL library
+    material: Option<Spanned<String>>,
+}
+
+// An error at a byte offset in the scene file
+type SectionError = (usize, String);
+
+// Check that a section only has the keys of its type, `given` being where the
+// value of each key set is
+fn check_keys(
+    kind: &Spanned<String>,
+    allowed: &[&str],
+    given: &[(&str, Option<Range<usize>>)],
+) -> Result<(), SectionError> {
+    for (key, span) in given {
+        if let Some(span) = span {
+            if !allowed.contains(key) {
+                let message = format!("`{}` doesn't take a `{key}`", kind.get_ref());
+                return Err((span.start, message));
+            }
+        }
+    }
+    Ok(())
+}
+
+// The value of a key required by the type of a section
+fn required<'a, T>(
+    kind: &Spanned<String>,
+    value: &'a Option<Spanned<T>>,
+    key: &str,
+) -> Result<&'a T, SectionError> {
+    value.as_ref().map(Spanned::get_ref).ok_or_else(|| {
+        let message = format!("`{}` needs a `{key}`", kind.get_ref());
+        (kind.span().start, message)
+    })
+}
+
+fn unknown_type(kind: &Spanned<String>, types: &str) -> SectionError {
+    let message = format!("unknown type `{}`, expected one of {types}", kind.get_ref());
+    (kind.span().start, message)
+}
+
+fn span<T>(value: &Option<Spanned<T>>) -> Option<Range<usize>> {
+    value.as_ref().map(Spanned::span)
+}
+
+impl SkySection {
+    fn to_sky(&self) -> Result<Sky, SectionError> {
+        let kind = &self.kind;
+        let given = [
+            ("color", span(&self.color)),
+            ("bottom", span(&self.bottom)),
+            ("top", span(&self.top)),
+        ];
+        match kind.get_ref().as_str() {
+            "solid" => {
+                check_keys(kind, &["color"], &given)?;
+                Ok(Sky::Solid(vec3(required(kind, &self.color, "color")?)))
+            }
+            "gradient" => {
+                check_keys(kind, &["bottom", "top"], &given)?;
+                Ok(Sky::Gradient {
+                    bottom: vec3(required(kind, &self.bottom, "bottom")?),
+                    top: vec3(required(kind, &self.top, "top")?),
+                })
+            }
+            _ => Err(unknown_type(kind, "`solid`, `gradient`")),
+        }
+    }
+}
+
+impl MaterialSection {
+    fn to_material(&self) -> Result<MaterialObject, SectionError> {
+        let kind = &self.kind;
+        let given = [
+            ("albedo", span(&self.albedo)),
+            ("fuzz", span(&self.fuzz)),
+            ("refraction_i", span(&self.refraction_i)),
+            ("color", span(&self.color)),
+        ];
+        let albedo = || required(kind, &self.albedo, "albedo").map(vec3);
+        match kind.get_ref().as_str() {
+            "lambertian" => {
+                check_keys(kind, &["albedo"], &given)?;
+                Ok(Arc::new(Lambertian::new(albedo()?)))
+            }
+            "metal" => {
+                check_keys(kind, &["albedo", "fuzz"], &given)?;
+                let fuzz = self.fuzz.as_ref().map_or(0.0, |fuzz| *fuzz.get_ref());
+                Ok(Arc::new(Metal::new(albedo()?, fuzz)))
+            }
+            "dielectric" | "dielectic" => {
+                check_keys(kind, &["refraction_i"], &given)?;
+                let refraction_i = required(kind, &self.refraction_i, "refraction_i")?;
+                Ok(Arc::new(Dielectic::new(*refraction_i)))
+            }
+            "light" => {
+                check_keys(kind, &["color"], &given)?;
+                let color = required(kind, &self.color, "color")?;
+                Ok(Arc::new(LightSource::new(vec3(color))))
+            }
+            _ => Err(unknown_type(
+                kind,
+                "`lambertian`, `metal`, `dielectric`, `light`",
+            )),
+        }
+    }
+}
+
+// An object section checked against its type
+enum Object<'a> {
+    Sphere {
+        center: &'a [f32; 3],
+        radius: f32,
+        material: &'a Spanned<String>,
+    },
+    Triangle {
+        vertices: &'a [[f32; 3]; 3],
+        material: &'a Spanned<String>,
+    },
+    Obj {
+        path: &'a str,
+        material: Option<&'a Spanned<String>>,
+    },
+}
+
+impl ObjectSection {
+    fn to_object(&self) -> Result<Object<'_>, SectionError> {
+        let kind = &self.kind;
+        let given = [
+            ("center", span(&self.center)),
+            ("radius", span(&self.radius)),
+            ("vertices", span(&self.vertices)),
+            ("path", span(&self.path)),
+            ("material", span(&self.material)),
+        ];
+        let material = || {
+            self.material.as_ref().ok_or_else(|| {
+                let message = format!("`{}` needs a `material`", kind.get_ref());
+                (kind.span().start, message)
+            })
+        };
+        match kind.get_ref().as_str() {
+            "sphere" => {
+                check_keys(kind, &["center", "radius", "material"], &given)?;
+                Ok(Object::Sphere {
+                    center: required(kind, &self.center, "center")?,
+                    radius: *required(kind, &self.radius, "radius")?,
+                    material: material()?,
+                })
+            }
+            "triangle" => {
+                check_keys(kind, &["vertices", "material"], &given)?;
+                Ok(Object::Triangle {
+                    vertices: required(kind, &self.vertices, "vertices")?,
+                    material: material()?,
+                })
+            }
+            "obj" => {
+                check_keys(kind, &["path", "material"], &given)?;
+                Ok(Object::Obj {
+                    path: required(kind, &self.path, "path")?,
+                    material: self.material.as_ref(),
+                })
+            }
+            _ => Err(unknown_type(kind, "`sphere`, `triangle`, `obj`")),
+        }
+    }
+}
+
+pub fn parse_scene(path: impl AsRef<Path>) -> Result<SceneDescription, SceneError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| SceneError {
+        path: path.to_owned(),
+        line: None,
+        message: e.to_string(),
+    })?;
+    let file: SceneFile = toml::from_str(&source).map_err(|e| SceneError {
+        path: path.to_owned(),
+        line: e.span().map(|span| line_of(&source, span.start)),
+        message: e.message().to_string(),
+    })?;
+
+    // Same limits as on the command line
+    let at_least =
+        |value: &Option<Spanned<usize>>, name: &str, min: usize, default: usize| match value {
+            Some(value) if *value.get_ref() < min => Err(SceneError {
+                path: path.to_owned(),
+                line: Some(line_of(&source, value.span().start)),
+                message: format!("`{name}` must be at least {min}"),
+            }),
+            Some(value) => Ok(*value.get_ref()),
+            None => Ok(default),
+        };
+    let render = &file.render;
+    let defaults = RenderSettings::default();
+    let settings = RenderSettings {
+        width: at_least(&render.width, "width", 2, defaults.width)?,
+        height: at_least(&render.height, "height", 2, defaults.height)?,
+        samples_per_pixel: at_least(
+            &render.samples_per_pixel,
+            "samples_per_pixel",
+            1,
+            defaults.samples_per_pixel,
+        )?,
+        max_depth: at_least(&render.max_depth, "max_depth", 1, defaults.max_depth)?,
+        seed: render.seed.unwrap_or(defaults.seed),
+        ..defaults
+    };
+    Ok(SceneDescription {
+        settings,
+        output: render.output.clone(),
+        path: path.to_owned(),
+        source,
+        file,
+    })
+}
+
+impl SceneDescription {
+    pub fn build(self) -> Result<Scene, SceneError> {
+        self.build_with_digest().map(|(scene, _)| scene)
+    }
+
+    // Also returns a digest of the path and contents of the scene file and of
+    // every file it loads, to tell apart the scenes it described over time
+    pub fn build_with_digest(self) -> Result<(Scene, u64), SceneError> {
+        let SceneDescription {
+            settings,
+            path,
+            source,
+            file,
+            ..
+        } = self;
+        let error = |offset: usize, message: String| SceneError {
+            path: path.clone(),
+            line: Some(line_of(&source, offset)),
+            message,
+        };
+        // Each file is followed by its length, so their boundaries count
+        let mut digest = StableHasher::default();
+        let mut add_file = |contents: &str| {
+            digest.write(contents.as_bytes());
+            digest.write(&(contents.len() as u64).to_le_bytes());
+        };
+        add_file(&path.to_string_lossy());
+        add_file(&source);
+
+        let section_error = |(offset, message)| error(offset, message);
+
+        let materials = file
+            .materials
+            .iter()
+            .map(|(name, material)| Ok((name.as_str(), material.to_material()?)))
+            .collect::<Result<HashMap<&str, MaterialObject>, _>>()
+            .map_err(section_error)?;
+        let material = |name: &Spanned<String>| {
+            materials
+                .get(name.get_ref().as_str())
+                .cloned()
+                .ok_or_else(|| {
+                    let message = format!("unknown material `{}`", name.get_ref());
+                    error(name.span().start, message)
+                })
+        };
+
+        let mut world = HittableList::default();
+        for object in &file.objects {
+            let offset = object.span().start;
+            match object.get_ref().to_object().map_err(section_error)? {
+                Object::Sphere {
+                    center,
+                    radius,
+                    material: name,
+                } => world.add(Sphere::new(vec3(center), radius, material(name)?)),
+                Object::Triangle {
+                    vertices: [a, b, c],
+                    material: name,
+                } => world.add(Triangle::new(vec3(a), vec3(b), vec3(c), material(name)?)),
+                Object::Obj {
+                    path: obj_path,
+                    material: name,
+                } => {
+                    let override_material = name.map(material).transpose()?;
+                    let obj_path = path.parent().unwrap_or(Path::new("")).join(obj_path);
+                    let meshes = obj::load_obj_with(obj_path, &mut add_file)
+                        .map_err(|e| error(offset, e.to_string()))?;
+                    for mut mesh in meshes {
+                        if let Some(material) = &override_material {
+                            mesh.material = material.clone();
+                        }
+                        world.add(mesh.into_hittable());
+                    }
+                }
+            }
+        }
+
+        let camera = file.camera;
+        let look_from = vec3(&camera.look_from);
+        let look_at = vec3(&camera.look_at);
+        let camera = Camera::new(
+            look_from,
+            look_at,
+            vec3(&camera.vup),
+            camera.vfov,
+            settings.aspect_ratio(),
+            camera.aperture,
+            camera
+                .focus_dist
+                .unwrap_or_else(|| (look_from - look_at).norm()),
+        );
+
+        let sky = match &file.sky {
+            Some(sky) => sky.to_sky().map_err(section_error)?,
+            None => Sky::Solid(Color::zeros()),
+        };
+
+        Ok((Scene::new(world, camera, sky), digest.finish()))
+    }
+}
+
+// Line number, starting at 1, of a byte offset in the file
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+fn vec3(v: &[f32; 3]) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"[camera]
+look_from = [0, 0, 3]
+look_at = [0, 0, 0]
+vfov = 40
+
+[materials.red]
+type = "lambertian"
+albedo = [1, 0, 0]
+"#;
+
+    // Line of the error in a scene file made of `HEADER` and `objects`, either
+    // while parsing or while building it
+    fn error_line(name: &str, objects: &str) -> Option<usize> {
+        let path = std::env::temp_dir().join(format!("ray_tracing_{}_{name}", std::process::id()));
+        std::fs::write(&path, format!("{HEADER}{objects}")).unwrap();
+        let result = parse_scene(&path).and_then(SceneDescription::build);
+        std::fs::remove_file(&path).unwrap();
+        result.err().expect("the scene is invalid").line
+    }
+
+    #[test]
+    fn object_errors_point_to_the_key() {
+        let sphere = |radius: &str, material: &str| {
+            format!("\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\n{radius}\n{material}\n")
+        };
+        // The object starts on line 10, its radius is on line 13
+        let bad_value = sphere("radius = \"big\"", "material = \"red\"");
+        assert_eq!(error_line("bad_value.toml", &bad_value), Some(13));
+        let other_type_key = sphere("radius = 1", "path = \"model.obj\"");
+        assert_eq!(error_line("other_type.toml", &other_type_key), Some(14));
+        let unknown_material = sphere("radius = 1", "material = \"blue\"");
+        assert_eq!(error_line("material.toml", &unknown_material), Some(14));
+        // Missing keys are reported at the type of the object
+        let missing = sphere("", "material = \"red\"");
+        assert_eq!(error_line("missing.toml", &missing), Some(11));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::filter::Filter;
+use crate::sampler::SamplerKind;
+use crate::space_filler::TraversalOrder;
+
+// Settings that can change from one render to another
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderSettings {
+    pub width: usize,
+    pub height: usize,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+    pub seed: u64,
+    pub filter: Filter,
+    pub sampler: SamplerKind,
+    // Order in which the tiles of the image are rendered
+    pub order: TraversalOrder,
+    // Stop sampling pixels once they're converged instead of always taking
+    // `samples_per_pixel` samples
+    pub adaptive: Option<AdaptiveSettings>,
+    // Only render this part of the image
+    pub crop: Option<Crop>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSettings {
+    // Samples taken before looking at the noise of a pixel
+    pub min_samples: usize,
+    pub max_samples: usize,
+    // Pixels are converged once the standard error of their gamma encoded
+    // luminance is below this
+    pub noise_threshold: f32,
+}
+
+// A rectangle of pixels, `(x, y)` being its top left corner in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Crop {
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    // Whether the crop is inside a `width` × `height` image
+    pub fn fits_in(&self, width: usize, height: usize) -> bool {
+        self.x
+            .checked_add(self.width)
+            .is_some_and(|right| right <= width)
+            && self
+                .y
+                .checked_add(self.height)
+                .is_some_and(|bottom| bottom <= height)
+    }
+}
+
+impl RenderSettings {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    // The pixels rendered, the whole image unless it's cropped
+    pub fn region(&self) -> Crop {
+        self.crop.unwrap_or(Crop {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        })
+    }
+
+    // The region and the pixels around it whose samples reach it through the
+    // filter
+    pub fn padded_region(&self) -> Crop {
+        let region = self.region();
+        let reach = self.filter.reach();
+        let (x, y) = (
+            region.x.saturating_sub(reach),
+            region.y.saturating_sub(reach),
+        );
+        Crop {
+            x,
+            y,
+            width: (region.x + region.width + reach).min(self.width) - x,
+            height: (region.y + region.height + reach).min(self.height) - y,
+        }
+    }
+
+    // The most samples a pixel can get
+    pub fn max_samples_per_pixel(&self) -> usize {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.max_samples,
+            None => self.samples_per_pixel,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            width: 1200,
+            height: 800,
+            samples_per_pixel: 500,
+            max_depth: 50,
+            seed: crate::SEED,
+            filter: Filter::default(),
+            sampler: SamplerKind::default(),
+            order: TraversalOrder::default(),
+            adaptive: None,
+            crop: None,
+        }
+    }
+}
@@ -0,0 +1,23 @@
+use crate::ray::Ray;
+use crate::Color;
+
+// What a ray sees when it doesn't hit anything
+#[derive(Debug, Clone)]
+pub enum Sky {
+    Solid(Color),
+    // Blend between two colors depending on the height of the ray direction
+    Gradient { bottom: Color, top: Color },
+}
+
+impl Sky {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Sky::Solid(color) => *color,
+            Sky::Gradient { bottom, top } => {
+                let unit_dir = ray.dir.normalize();
+                let t = 0.5 * (unit_dir.y + 1.0);
+                bottom.lerp(top, t)
+            }
+        }
+    }
+}
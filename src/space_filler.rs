@@ -1,22 +1,157 @@
-// use rand::seq::SliceRandom;
-// use rand::thread_rng;
-
-use crate::*;
-
-static CURVE: Lazy<Vec<(usize, usize)>> = Lazy::new(|| {
-    let mut curve = Vec::new();
-    for xy in 0..(WIDTH * HEIGHT) {
-        curve.push({
-            let xy = (xy * 10) % (WIDTH * HEIGHT + 1);
-            let y = xy % HEIGHT;
-            let x = xy / HEIGHT;
-            (x, y)
-        });
+// Orders in which the cells of a `width` × `height` grid, the tiles of the
+// image, can be rendered. Every order visits each cell exactly once, whatever
+// the size of the grid.
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraversalOrder {
+    // Neighbor cells stay close to each other in time, good for caches
+    Hilbert,
+    // Z-order, like Hilbert but with jumps between quadrants
+    Morton,
+    // From the center of the image outward, the subject usually comes first
+    Spiral,
+    // Rows, left to right and top to bottom
+    Scanline,
+    // Random, the whole image fills in evenly
+    #[default]
+    Shuffled,
+}
+
+impl TraversalOrder {
+    // Every cell of the grid in this order, as (x, y). `seed` is only used by
+    // the shuffled order
+    pub fn cells(self, width: usize, height: usize, seed: u64) -> Vec<(usize, usize)> {
+        match self {
+            TraversalOrder::Hilbert => covering_curve(width, height, hilbert),
+            TraversalOrder::Morton => covering_curve(width, height, morton),
+            TraversalOrder::Spiral => spiral(width, height),
+            TraversalOrder::Scanline => scanline(width, height),
+            TraversalOrder::Shuffled => {
+                let mut cells = scanline(width, height);
+                cells.shuffle(&mut StdRng::seed_from_u64(seed));
+                cells
+            }
+        }
+    }
+}
+
+fn scanline(width: usize, height: usize) -> Vec<(usize, usize)> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .collect()
+}
+
+// Curves only defined on power of two squares are run on the smallest square
+// covering the grid, skipping what falls outside of it
+fn covering_curve(
+    width: usize,
+    height: usize,
+    curve: fn(usize, usize) -> (usize, usize),
+) -> Vec<(usize, usize)> {
+    let side = width.max(height).next_power_of_two();
+    (0..side * side)
+        .map(|d| curve(side, d))
+        .filter(|&(x, y)| x < width && y < height)
+        .collect()
+}
+
+// The `d`th point of the Hilbert curve filling a `side` × `side` square
+fn hilbert(side: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+    while s < side {
+        let rx = (t / 2) & 1;
+        let ry = (t ^ rx) & 1;
+        // Rotate the quadrant so the curve connects with the next one
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+    (x, y)
+}
+
+// The `d`th point of the Z-order curve, bits of `d` alternate between x and y
+fn morton(_side: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    for bit in 0..usize::BITS as usize / 2 {
+        x |= ((d >> (2 * bit)) & 1) << bit;
+        y |= ((d >> (2 * bit + 1)) & 1) << bit;
+    }
+    (x, y)
+}
+
+// Square spiral around the center, going 1 step, turning, 1 step, turning, 2
+// steps, turning, 2 steps... until it went through every cell of the grid
+fn spiral(width: usize, height: usize) -> Vec<(usize, usize)> {
+    let count = width * height;
+    let mut cells = Vec::with_capacity(count);
+    if count == 0 {
+        return cells;
+    }
+    let (mut x, mut y) = ((width as isize - 1) / 2, (height as isize - 1) / 2);
+    let (mut dx, mut dy) = (1, 0);
+    let mut length = 1;
+    cells.push((x as usize, y as usize));
+    while cells.len() < count {
+        for _ in 0..2 {
+            for _ in 0..length {
+                x += dx;
+                y += dy;
+                if (0..width as isize).contains(&x) && (0..height as isize).contains(&y) {
+                    cells.push((x as usize, y as usize));
+                }
+            }
+            (dx, dy) = (-dy, dx);
+        }
+        length += 1;
     }
-    // curve.shuffle(&mut thread_rng());
-    curve
-});
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [TraversalOrder; 5] = [
+        TraversalOrder::Hilbert,
+        TraversalOrder::Morton,
+        TraversalOrder::Spiral,
+        TraversalOrder::Scanline,
+        TraversalOrder::Shuffled,
+    ];
 
-pub fn filling_curve(xy: usize) -> (usize, usize) {
-    *CURVE.get(xy).unwrap()
+    #[test]
+    fn orders_are_permutations() {
+        let sizes = (0..=17).flat_map(|width| (0..=17).map(move |height| (width, height)));
+        for (width, height) in sizes.chain([(38, 25), (64, 64), (1, 100), (100, 3)]) {
+            for order in ORDERS {
+                let mut cells = order.cells(width, height, 123);
+                assert_eq!(cells.len(), width * height, "{order:?} {width}x{height}");
+                cells.sort_by_key(|&(x, y)| (y, x));
+                assert_eq!(cells, scanline(width, height), "{order:?} {width}x{height}");
+            }
+        }
+    }
+
+    #[test]
+    fn hilbert_steps_to_neighbors() {
+        let cells = TraversalOrder::Hilbert.cells(16, 16, 0);
+        for pair in cells.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert_eq!(a.0.abs_diff(b.0) + a.1.abs_diff(b.1), 1);
+        }
+    }
 }
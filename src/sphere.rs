@@ -1,3 +1,4 @@
+use crate::aabb::Aabb;
 use crate::hittable::*;
 use crate::material::MaterialObject;
 use crate::ray;
@@ -42,10 +43,27 @@ impl Hittable for Sphere {
         let rec = HitRecord::new_with_front_face(
             ray.at(root),
             root,
+            sphere_uv(&outward_normal),
             self.material.clone(),
             ray,
             &outward_normal,
         );
         Some(rec)
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The radius can be negative to make hollow spheres
+        let radius = glm::vec3(self.radius, self.radius, self.radius).abs();
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+}
+
+// Longitude and latitude of a point on the unit sphere, mapped to [0, 1]
+fn sphere_uv(point: &glm::Vec3) -> glm::Vec2 {
+    let theta = (-point.y).acos();
+    let phi = (-point.z).atan2(point.x) + std::f32::consts::PI;
+    glm::vec2(
+        phi / (2.0 * std::f32::consts::PI),
+        theta / std::f32::consts::PI,
+    )
 }
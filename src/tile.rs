@@ -0,0 +1,55 @@
+// The image is rendered in square tiles, each one by a single thread in its
+// own buffer, so threads only meet when a finished tile is handed over.
+
+use crate::settings::Crop;
+use crate::space_filler::TraversalOrder;
+
+pub const TILE_SIZE: usize = 32;
+
+// A rectangle of pixels, the tiles on the right and bottom edges of the image
+// can be smaller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    // Pixels of the tile, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+// Every tile of a region of the image, in rendering order. Tiles follow the
+// grid of the whole image, cut at the edges of the region, so a pixel is
+// rendered with the same neighbors whatever the region
+pub fn tiles(region: &Crop, order: TraversalOrder, seed: u64) -> Vec<Tile> {
+    let (x0, y0) = (
+        region.x / TILE_SIZE * TILE_SIZE,
+        region.y / TILE_SIZE * TILE_SIZE,
+    );
+    let (x1, y1) = (region.x + region.width, region.y + region.height);
+    let (columns, rows) = ((x1 - x0).div_ceil(TILE_SIZE), (y1 - y0).div_ceil(TILE_SIZE));
+    order
+        .cells(columns, rows, seed)
+        .into_iter()
+        .map(|(column, row)| {
+            let (x, y) = (x0 + column * TILE_SIZE, y0 + row * TILE_SIZE);
+            let (left, top) = (x.max(region.x), y.max(region.y));
+            Tile {
+                x: left,
+                y: top,
+                width: (x + TILE_SIZE).min(x1) - left,
+                height: (y + TILE_SIZE).min(y1) - top,
+            }
+        })
+        .collect()
+}
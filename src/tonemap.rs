@@ -0,0 +1,172 @@
+// Tone mapping operators, turning the unbounded radiance of a render into
+// values between 0 and 1 that a screen or an 8 bits image can show. They work
+// on linear values, the gamma encoding happens afterwards.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::color::luminance;
+use crate::Color;
+
+pub trait ToneMapper: fmt::Debug + Send + Sync {
+    // Map a linear radiance to a linear display value between 0 and 1
+    fn map(&self, color: Color) -> Color;
+}
+
+// An exposure and an operator, what's needed to turn a render into an
+// image to look at
+#[derive(Debug, Clone)]
+pub struct ToneMapping {
+    // In stops, every stop doubles the brightness
+    pub exposure: f32,
+    pub operator: Arc<dyn ToneMapper>,
+}
+
+impl ToneMapping {
+    pub fn new(exposure: f32, operator: impl ToneMapper + 'static) -> Self {
+        Self {
+            exposure,
+            operator: Arc::new(operator),
+        }
+    }
+
+    pub fn apply(&self, color: Color) -> Color {
+        self.operator.map(color * self.exposure.exp2())
+    }
+}
+
+// Clip everything above 1, like the renderer always did
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self::new(0.0, Clamp)
+    }
+}
+
+// Scale a color so its luminance becomes `new_luminance`, keeping its hue
+fn with_luminance(color: Color, new_luminance: f32) -> Color {
+    let luminance = luminance(&color);
+    if luminance <= 0.0 {
+        return Color::zeros();
+    }
+    color * (new_luminance / luminance)
+}
+
+fn clamp01(color: Color) -> Color {
+    color.map(|c| c.clamp(0.0, 1.0))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Clamp;
+
+impl ToneMapper for Clamp {
+    fn map(&self, color: Color) -> Color {
+        clamp01(color)
+    }
+}
+
+// L / (1 + L) on the luminance, never quite reaches white
+#[derive(Debug, Clone, Copy)]
+pub struct Reinhard;
+
+impl ToneMapper for Reinhard {
+    fn map(&self, color: Color) -> Color {
+        let l = luminance(&color);
+        clamp01(with_luminance(color, l / (1.0 + l)))
+    }
+}
+
+// Reinhard with a white point, the luminance that maps to 1
+#[derive(Debug, Clone, Copy)]
+pub struct ExtendedReinhard {
+    pub white: f32,
+}
+
+impl ToneMapper for ExtendedReinhard {
+    fn map(&self, color: Color) -> Color {
+        let l = luminance(&color);
+        let mapped = l * (1.0 + l / (self.white * self.white)) / (1.0 + l);
+        clamp01(with_luminance(color, mapped))
+    }
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+#[derive(Debug, Clone, Copy)]
+pub struct Aces;
+
+impl ToneMapper for Aces {
+    fn map(&self, color: Color) -> Color {
+        let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+        // The fit is made for an exposure of 0.6
+        color.map(|x| {
+            let x = x * 0.6;
+            ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+        })
+    }
+}
+
+// John Hable's filmic curve from Uncharted 2
+#[derive(Debug, Clone, Copy)]
+pub struct Hable;
+
+impl Hable {
+    const WHITE: f32 = 11.2;
+    const EXPOSURE_BIAS: f32 = 2.0;
+
+    fn curve(x: f32) -> f32 {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+}
+
+impl ToneMapper for Hable {
+    fn map(&self, color: Color) -> Color {
+        let white_scale = 1.0 / Self::curve(Self::WHITE);
+        color.map(|x| (Self::curve(x * Self::EXPOSURE_BIAS) * white_scale).clamp(0.0, 1.0))
+    }
+}
+
+// Troy Sobotka's AgX, with the polynomial fit of its sigmoid by Benjamin
+// Wrensch. Desaturates very bright colors instead of skewing their hue
+#[derive(Debug, Clone, Copy)]
+pub struct AgX;
+
+impl AgX {
+    const MIN_EV: f32 = -12.47393;
+    const MAX_EV: f32 = 4.026069;
+
+    #[rustfmt::skip]
+    fn inset() -> glm::Mat3 {
+        glm::mat3(
+            0.8424791, 0.0784336, 0.0792237,
+            0.0423282, 0.8784686, 0.0791661,
+            0.0423757, 0.0784336, 0.879143,
+        )
+    }
+
+    #[rustfmt::skip]
+    fn outset() -> glm::Mat3 {
+        glm::mat3(
+            1.196879, -0.0980209, -0.0990297,
+            -0.0528969, 1.1519031, -0.0989612,
+            -0.0529716, -0.0980435, 1.1510737,
+        )
+    }
+
+    fn contrast(x: f32) -> f32 {
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    }
+}
+
+impl ToneMapper for AgX {
+    fn map(&self, color: Color) -> Color {
+        let encoded = (Self::inset() * color).map(|c| {
+            let ev = c.max(1e-10).log2().clamp(Self::MIN_EV, Self::MAX_EV);
+            Self::contrast((ev - Self::MIN_EV) / (Self::MAX_EV - Self::MIN_EV))
+        });
+        // The curve outputs gamma 2.2 values
+        (Self::outset() * encoded).map(|c| c.max(0.0).powf(2.2).min(1.0))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::aabb::Aabb;
+use crate::hittable::*;
+use crate::material::MaterialObject;
+use crate::ray::Ray;
+
+pub struct Triangle {
+    pub vertices: [glm::Vec3; 3],
+    pub material: MaterialObject,
+}
+
+impl Triangle {
+    pub fn new(a: glm::Vec3, b: glm::Vec3, c: glm::Vec3, material: MaterialObject) -> Box<Self> {
+        Box::new(Self {
+            vertices: [a, b, c],
+            material,
+        })
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+        let [a, b, c] = &self.vertices;
+        let (t, b1, b2) = intersect(ray, a, b, c, t_min, t_max)?;
+        let outward_normal = (b - a).cross(&(c - a)).normalize();
+        Some(HitRecord::new_with_front_face(
+            ray.at(t),
+            t,
+            glm::vec2(b1, b2),
+            self.material.clone(),
+            ray,
+            &outward_normal,
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_box(&self.vertices))
+    }
+}
+
+// Möller–Trumbore intersection. Returns the distance along the ray and the
+// barycentric coordinates of the hit point relative to `b` and `c`
+pub fn intersect(
+    ray: &Ray,
+    a: &glm::Vec3,
+    b: &glm::Vec3,
+    c: &glm::Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.dir.cross(&edge2);
+    let det = edge1.dot(&p);
+    // The ray is parallel to the triangle, or the triangle is degenerate.
+    // Relative to the size of the triangle and of the ray direction, small
+    // triangles are still hit
+    if det * det <= f32::EPSILON * f32::EPSILON * edge1.norm_squared() * p.norm_squared() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = ray.origin - a;
+    let b1 = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let b2 = ray.dir.dot(&q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&q) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+pub fn triangle_box(vertices: &[glm::Vec3; 3]) -> Aabb {
+    let [a, b, c] = vertices;
+    // Pad the box so axis aligned triangles don't end up with a flat box
+    let padding = glm::vec3(1e-4, 1e-4, 1e-4);
+    Aabb::new(glm::min3(a, b, c) - padding, glm::max3(a, b, c) + padding)
+}